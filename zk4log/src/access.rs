@@ -0,0 +1,144 @@
+//! Parser for web server access logs.
+//!
+//! Lines are parsed with an nginx `log_format` string such as the Combined Log
+//! Format used by both Apache and nginx. Every `$variable` of the format
+//! becomes a column named after the variable (`remote_addr`, `request`, ...),
+//! holding the text of the line at that place.
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// The Combined Log Format, nginx's default `combined` log format.
+pub const COMBINED: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+#[derive(Debug, Clone)]
+pub struct LogFormat {
+    regex: Regex,
+    columns: Vec<String>,
+}
+
+impl LogFormat {
+    /// Compiles an nginx `log_format` string.
+    pub fn new(format: &str) -> Result<LogFormat, String> {
+        let mut pattern = String::from("^");
+        let mut columns: Vec<String> = Vec::new();
+        let mut rest = format;
+
+        while let Some(start) = rest.find('$') {
+            pattern.push_str(&regex::escape(&rest[..start]));
+            let after = &rest[start + 1..];
+            let (name, after) = match after.strip_prefix('{') {
+                Some(braced) => braced
+                    .split_once('}')
+                    .ok_or_else(|| format!("unterminated variable in {}", format))?,
+                None => {
+                    let end = after
+                        .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                        .unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            if name.is_empty() {
+                return Err(format!("empty variable name in {}", format));
+            }
+            if columns.iter().any(|c| c == name) {
+                return Err(format!("variable ${} appears twice in {}", name, format));
+            }
+
+            // Inside quotes a value ends at the closing quote (quotes in the
+            // value are escaped); anywhere else at the following text.
+            if after.starts_with('"') {
+                pattern.push_str(r#"((?:[^"\\]|\\.)*)"#);
+            } else {
+                pattern.push_str("(.*?)");
+            }
+            columns.push(name.to_string());
+            rest = after;
+        }
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+
+        if columns.is_empty() {
+            return Err(format!("no variables in {}", format));
+        }
+        let regex = Regex::new(&pattern).map_err(|e| e.to_string())?;
+        Ok(LogFormat { regex, columns })
+    }
+
+    pub fn combined() -> LogFormat {
+        LogFormat::new(COMBINED).expect("the combined log format is valid")
+    }
+
+    /// Parses one line of the log.
+    pub fn parse(&self, line: &str) -> Result<Value, String> {
+        let captures = self
+            .regex
+            .captures(line)
+            .ok_or("does not match the log format")?;
+        let record: Map<String, Value> = self
+            .columns
+            .iter()
+            .zip(captures.iter().skip(1))
+            .map(|(column, value)| {
+                let value = value.map_or("", |m| m.as_str());
+                (column.clone(), Value::from(value))
+            })
+            .collect();
+        Ok(Value::Object(record))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_combined() {
+        let line = r#"192.0.2.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /login?token=abc HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#;
+        assert_eq!(
+            LogFormat::combined().parse(line).unwrap(),
+            json!({
+                "remote_addr": "192.0.2.1",
+                "remote_user": "frank",
+                "time_local": "10/Oct/2000:13:55:36 -0700",
+                "request": "GET /login?token=abc HTTP/1.0",
+                "status": "200",
+                "body_bytes_sent": "2326",
+                "http_referer": "http://www.example.com/start.html",
+                "http_user_agent": "Mozilla/4.08 [en] (Win98; I ;Nav)",
+            })
+        );
+
+        let line = r#"192.0.2.1 - - [10/Oct/2000:13:55:36 -0700] "GET /\"quoted\" HTTP/1.1" 404 0 "-" "curl/8.0""#;
+        let record = LogFormat::combined().parse(line).unwrap();
+        assert_eq!(record["request"], json!(r#"GET /\"quoted\" HTTP/1.1"#));
+        assert_eq!(record["http_user_agent"], json!("curl/8.0"));
+
+        assert!(LogFormat::combined().parse("not an access log").is_err());
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let format =
+            LogFormat::new(r#"$remote_addr [$time_iso8601] "$request" ${status}/$request_time rt=$upstream_response_time"#)
+                .unwrap();
+        assert_eq!(
+            format
+                .parse(r#"2001:db8::1 [2023-10-11T22:14:15+09:00] "POST /api HTTP/2.0" 201/0.005 rt=0.004"#)
+                .unwrap(),
+            json!({
+                "remote_addr": "2001:db8::1",
+                "time_iso8601": "2023-10-11T22:14:15+09:00",
+                "request": "POST /api HTTP/2.0",
+                "status": "201",
+                "request_time": "0.005",
+                "upstream_response_time": "0.004",
+            })
+        );
+
+        assert!(LogFormat::new("no variables").is_err());
+        assert!(LogFormat::new("$a $a").is_err());
+        assert!(LogFormat::new("${a").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Bundles holding everything needed to verify a hidden log.
+//!
+//! A bundle is a tar archive of a manifest, the hidden log, its proof file and
+//! the verifying key the proofs were made for:
+//!
+//! ```text
+//! manifest.json   tool version, hashes, circuits, hidden columns, timestamps
+//!                 and the SHA-256 of the other files
+//! log.ndjson      the hidden log, named after the format it is written in
+//! log.proof       the proof file (see `proof_file`)
+//! key.pub         the verifying key of every hash (see `keys::write_vk`)
+//! signature.json  optional signature over manifest.json (see `signing`)
+//! ```
+//!
+//! Bundles are written from and unpacked into a `TempDir`, so that the hidden
+//! log and the proof file can still be written and read record by record.
+
+use crate::records::Format;
+use crate::signing::{self, Signature};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+/// Value of the `format` field, identifying zk4log bundles.
+pub const FORMAT: &str = "zk4log-bundle";
+/// Current version of the bundle format.
+pub const VERSION: u32 = 3;
+
+pub const MANIFEST: &str = "manifest.json";
+pub const PROOF: &str = "log.proof";
+pub const KEY: &str = "key.pub";
+pub const SIGNATURE: &str = "signature.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    /// Name and version of the tool that made the bundle.
+    pub tool: String,
+    /// Circuit the proofs were made for, by the name of the hash the cells
+    /// were hidden with (see `proof_file::Header::circuits`).
+    pub circuits: BTreeMap<String, String>,
+    pub log_id: String,
+    /// Columns hidden in at least one record.
+    pub columns: Vec<String>,
+    /// File name of the hidden log in the bundle.
+    pub log: String,
+    /// Format of the hidden log, as given to `--format`.
+    pub log_format: String,
+    /// Delimiter of CSV logs other than `,` and tab.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+    /// When the log was hidden, in RFC 3339.
+    pub created: String,
+    /// When the original log file was last modified, in RFC 3339. Not set for
+    /// tables hidden from the pipeline.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_modified: Option<String>,
+    /// Hex encoded SHA-256 of the hidden log, the proof file and the
+    /// verifying key, filled in by `write`.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Manifest for a hidden log written in `format`, i.e. `Format::output`.
+    pub fn new(
+        circuits: BTreeMap<String, String>,
+        log_id: String,
+        columns: Vec<String>,
+        format: &Format,
+    ) -> Manifest {
+        let (log_format, delimiter) = match format {
+            Format::Json => ("json", None),
+            Format::Csv(b',') => ("csv", None),
+            Format::Csv(b'\t') => ("tsv", None),
+            Format::Csv(delimiter) => ("csv", Some(*delimiter as char)),
+            Format::CloudTrail => ("cloudtrail", None),
+            _ => ("ndjson", None),
+        };
+        Manifest {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            tool: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            circuits,
+            log_id,
+            columns,
+            log: format!("log.{}", format.extension()),
+            log_format: log_format.to_string(),
+            delimiter,
+            created: chrono::Utc::now().to_rfc3339(),
+            log_modified: None,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Format of the hidden log in the bundle.
+    pub fn log_format(&self) -> Option<Format> {
+        match (Format::parse(&self.log_format), self.delimiter) {
+            (Some(Format::Csv(_)), Some(delimiter)) if delimiter.is_ascii() => {
+                Some(Format::Csv(delimiter as u8))
+            }
+            (format, None) => format,
+            _ => None,
+        }
+    }
+
+    fn file_names(&self) -> [&str; 3] {
+        [self.log.as_str(), PROOF, KEY]
+    }
+}
+
+/// An unpacked bundle.
+pub struct Bundle {
+    /// Directory holding the files of the bundle.
+    pub dir: TempDir,
+    pub manifest: Manifest,
+    /// Key the manifest was signed with, or why the signature is invalid.
+    /// `None` for unsigned bundles.
+    pub signer: Option<Result<VerifyingKey, String>>,
+}
+
+/// A directory under the system temporary directory, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> io::Result<TempDir> {
+        let name = format!(
+            "zk4log-{}",
+            hex::encode(rand::thread_rng().gen::<[u8; 8]>())
+        );
+        let dir = std::env::temp_dir().join(name);
+        fs::create_dir(&dir)?;
+        Ok(TempDir(dir))
+    }
+
+    /// Path of the file `name` in the directory.
+    pub fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn digest(path: &str) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn append_data<W: Write>(builder: &mut tar::Builder<W>, name: &str, data: &[u8]) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(chrono::Utc::now().timestamp() as u64);
+    header.set_cksum();
+    builder.append_data(&mut header, name, data)
+}
+
+/// Writes a bundle of `manifest` and the hidden log, proof file and verifying
+/// key in `dir`, signing the manifest with `sign_key` if given.
+pub fn write(
+    path: &str,
+    manifest: &Manifest,
+    dir: &TempDir,
+    sign_key: Option<&SigningKey>,
+) -> io::Result<()> {
+    let mut manifest = manifest.clone();
+    for name in manifest.file_names().map(str::to_string) {
+        let digest = digest(&dir.path(&name))?;
+        manifest.files.insert(name, digest);
+    }
+
+    let mut builder = tar::Builder::new(BufWriter::new(fs::File::create(path)?));
+    let data = serde_json::to_vec_pretty(&manifest)?;
+    append_data(&mut builder, MANIFEST, &data)?;
+    if let Some(sign_key) = sign_key {
+        let signature = serde_json::to_vec_pretty(&signing::sign(sign_key, &data))?;
+        append_data(&mut builder, SIGNATURE, &signature)?;
+    }
+    for name in manifest.file_names() {
+        builder.append_path_with_name(dir.path(name), name)?;
+    }
+    builder.into_inner()?.flush()
+}
+
+/// Unpacks the bundle at `path` into a new `TempDir`, rejecting bundles of an
+/// unknown format or version and bundles whose files are missing or do not
+/// match the manifest. The signature, if any, is checked but not trusted.
+pub fn unpack(path: &str) -> io::Result<Bundle> {
+    let dir = TempDir::new()?;
+    let mut archive = tar::Archive::new(fs::File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        // Only regular files with plain names, so that a bundle cannot write
+        // or link outside `dir`.
+        let mut components = name.components();
+        let (Some(Component::Normal(name)), None, true) = (
+            components.next(),
+            components.next(),
+            entry.header().entry_type().is_file(),
+        ) else {
+            return Err(invalid_data(format!(
+                "unexpected file {} in bundle",
+                name.display()
+            )));
+        };
+        entry.unpack(dir.0.join(name))?;
+    }
+
+    let data = fs::read(dir.path(MANIFEST))
+        .map_err(|_| invalid_data("not a zk4log bundle (no manifest)".to_string()))?;
+    let manifest: Manifest =
+        serde_json::from_slice(&data).map_err(|e| invalid_data(format!("{}: {}", MANIFEST, e)))?;
+    if manifest.format != FORMAT {
+        return Err(invalid_data(format!("not a {} file", FORMAT)));
+    }
+    if manifest.version != VERSION {
+        return Err(invalid_data(format!(
+            "unsupported bundle version {}",
+            manifest.version
+        )));
+    }
+    if Path::new(&manifest.log).components().count() != 1 {
+        return Err(invalid_data(format!("invalid log name {}", manifest.log)));
+    }
+    for name in manifest.file_names() {
+        if !Path::new(&dir.path(name)).is_file() {
+            return Err(invalid_data(format!("{} is missing from the bundle", name)));
+        }
+        if manifest.files.get(name) != Some(&digest(&dir.path(name))?) {
+            return Err(invalid_data(format!(
+                "{} does not match the manifest",
+                name
+            )));
+        }
+    }
+
+    let signer = match fs::read(dir.path(SIGNATURE)) {
+        Ok(signature) => Some(
+            serde_json::from_slice::<Signature>(&signature)
+                .map_err(|e| e.to_string())
+                .and_then(|signature| signature.verify(&data)),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    Ok(Bundle {
+        dir,
+        manifest,
+        signer,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let tmp = TempDir::new().unwrap();
+        let path = &tmp.path("test.bundle");
+
+        let mut manifest = Manifest::new(
+            BTreeMap::from([("sha256d".to_string(), "test".to_string())]),
+            "log".to_string(),
+            vec!["user::ip".to_string()],
+            &Format::Csv(b';'),
+        );
+        manifest.log_modified = Some("2023-10-01T00:00:00+00:00".to_string());
+        assert_eq!(manifest.log, "log.csv");
+        assert!(matches!(manifest.log_format(), Some(Format::Csv(b';'))));
+
+        let dir = TempDir::new().unwrap();
+        for (name, data) in [("log.csv", "ip\n<hash>\n"), (PROOF, "proof"), (KEY, "key")] {
+            fs::write(dir.path(name), data).unwrap();
+        }
+        let sign_key = signing::generate();
+        write(path, &manifest, &dir, Some(&sign_key)).unwrap();
+        drop(dir);
+
+        let Bundle {
+            dir,
+            manifest: read,
+            signer,
+        } = unpack(path).unwrap();
+        assert_eq!(read.files.len(), 3);
+        assert_eq!(read.files[PROOF], hex::encode(Sha256::digest(b"proof")));
+        assert_eq!(
+            Manifest {
+                files: BTreeMap::new(),
+                ..read
+            },
+            manifest
+        );
+        assert_eq!(signer, Some(Ok(sign_key.verifying_key())));
+        assert_eq!(fs::read_to_string(dir.path(PROOF)).unwrap(), "proof");
+        assert_eq!(
+            fs::read_to_string(dir.path("log.csv")).unwrap(),
+            "ip\n<hash>\n"
+        );
+
+        let repack = |names: &[&str]| {
+            let mut builder = tar::Builder::new(fs::File::create(path).unwrap());
+            for name in names {
+                builder.append_path_with_name(dir.path(name), name).unwrap();
+            }
+            builder.finish().unwrap();
+        };
+
+        // A changed manifest no longer matches its signature.
+        let mut changed: Manifest =
+            serde_json::from_slice(&fs::read(dir.path(MANIFEST)).unwrap()).unwrap();
+        changed.log_id = "other".to_string();
+        fs::write(dir.path(MANIFEST), serde_json::to_vec(&changed).unwrap()).unwrap();
+        repack(&[MANIFEST, SIGNATURE, "log.csv", PROOF, KEY]);
+        assert!(matches!(unpack(path).unwrap().signer, Some(Err(_))));
+        repack(&[MANIFEST, "log.csv", PROOF, KEY]);
+        assert_eq!(unpack(path).unwrap().signer, None);
+
+        // A changed proof file does not match the manifest.
+        fs::write(dir.path(PROOF), "other proof").unwrap();
+        repack(&[MANIFEST, "log.csv", PROOF, KEY]);
+        assert!(unpack(path).is_err());
+
+        // A bundle without its proof file is rejected.
+        repack(&[MANIFEST, "log.csv", KEY]);
+        assert!(unpack(path).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! Column selection from the command line.
+//!
+//! Columns are chosen with `--columns` (columns to take) or `--all-except`
+//! (columns to leave out). Both take a comma separated list of column names or
+//! glob patterns such as `user*` or `http.?`. Nested values are named by their
+//! path (`user.ip` or `/user/ip`, see `path`).
+
+use crate::path;
+use glob::Pattern;
+
+/// Parses a comma separated list of column names or glob patterns.
+pub fn parse_patterns(list: &str) -> Result<Vec<Pattern>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            Pattern::new(&path::canonical(p))
+                .map_err(|e| format!("invalid column pattern {}: {}", p, e))
+        })
+        .collect()
+}
+
+/// Whether `column` matches any of `patterns`.
+pub fn matches_any(patterns: &[Pattern], column: &str) -> bool {
+    patterns.iter().any(|p| p.matches(column))
+}
+
+/// Selects columns from `map_keys` and returns their indices, or `None` when
+/// neither `columns` nor `all_except` is given and the user has to choose.
+///
+/// A pattern of `columns` that matches no column is an error, so that a typo
+/// does not silently leave a column in clear text.
+pub fn select(
+    map_keys: &[String],
+    columns: Option<&str>,
+    all_except: Option<&str>,
+) -> Result<Option<Vec<usize>>, String> {
+    if columns.is_none() && all_except.is_none() {
+        return Ok(None);
+    }
+
+    let include = columns.map(parse_patterns).transpose()?;
+    let exclude = all_except
+        .map(parse_patterns)
+        .transpose()?
+        .unwrap_or_default();
+
+    if let Some(ref include) = include {
+        if let Some(p) = include
+            .iter()
+            .find(|p| !map_keys.iter().any(|k| p.matches(k)))
+        {
+            return Err(format!("no column matches {}", p));
+        }
+    }
+
+    Ok(Some(
+        map_keys
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| include.as_ref().is_none_or(|i| matches_any(i, k)))
+            .filter(|(_, k)| !matches_any(&exclude, k))
+            .map(|(i, _)| i)
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let keys: Vec<String> = ["time", "user", "user_agent", "ip", "status"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+
+        assert_eq!(select(&keys, None, None), Ok(None));
+        assert_eq!(select(&keys, Some("ip, user"), None), Ok(Some(vec![1, 3])));
+        assert_eq!(select(&keys, Some("user*"), None), Ok(Some(vec![1, 2])));
+        assert_eq!(
+            select(&keys, None, Some("time,status")),
+            Ok(Some(vec![1, 2, 3]))
+        );
+        assert_eq!(
+            select(&keys, Some("user*"), Some("user_agent")),
+            Ok(Some(vec![1]))
+        );
+        assert!(select(&keys, Some("usr"), None).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Commitments to whole records.
+//!
+//! A record is committed to by the root of a Merkle tree over its leaf cells
+//! (see `path::leaves`) in document order. Every leaf hashes the name of the
+//! cell together with its JSON value, so the commitment changes when a value
+//! is edited, when cells are added, removed or renamed, and when keys are
+//! reordered. Hidden cells take part with their hash.
+//!
+//! The log as a whole is committed to by a hash chain over the commitments of
+//! its records in order, together with the number of records, so that
+//! deleting, inserting or reordering records changes it as well. Every link
+//! also holds the names of the cells hidden in the record, so that a hidden
+//! cell cannot be passed off as a visible one by dropping its proof.
+
+use crate::path;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// Length of a commitment in bytes.
+pub const BYTES: usize = 32;
+
+/// Root of the Merkle tree over the cells of `record`.
+pub fn record(record: &Value) -> [u8; BYTES] {
+    let mut level: Vec<[u8; BYTES]> = path::leaves(record)
+        .into_iter()
+        .map(|(name, value)| leaf(&name, value))
+        .collect();
+    if level.is_empty() {
+        return Sha256::digest([]).into();
+    }
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node(left, right),
+                // An odd node moves up unchanged.
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Hash chain over the commitments of the records of a log.
+#[derive(Debug, Clone)]
+pub struct Chain {
+    head: [u8; BYTES],
+    count: usize,
+}
+
+impl Chain {
+    /// `SHA-256(0x02 || log_id)`
+    pub fn new(log_id: &str) -> Chain {
+        let mut hasher = Sha256::new();
+        hasher.update([2]);
+        hasher.update(log_id.as_bytes());
+        Chain {
+            head: hasher.finalize().into(),
+            count: 0,
+        }
+    }
+
+    /// Appends the commitment of the next record and the names of its hidden
+    /// cells, and returns the new head, `SHA-256(0x03 || head || record ||
+    /// (len(name) (u64 BE) || name for every hidden cell))`.
+    pub fn push(&mut self, record: &[u8; BYTES], hidden: &[String]) -> [u8; BYTES] {
+        let mut hasher = Sha256::new();
+        hasher.update([3]);
+        hasher.update(self.head);
+        hasher.update(record);
+        for name in hidden {
+            hasher.update((name.len() as u64).to_be_bytes());
+            hasher.update(name.as_bytes());
+        }
+        self.head = hasher.finalize().into();
+        self.count += 1;
+        self.head
+    }
+
+    pub fn head(&self) -> [u8; BYTES] {
+        self.head
+    }
+
+    /// Number of records in the chain.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+/// `SHA-256(0x00 || len(name) (u64 BE) || name || value as JSON)`
+fn leaf(name: &str, value: &Value) -> [u8; BYTES] {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update((name.len() as u64).to_be_bytes());
+    hasher.update(name.as_bytes());
+    hasher.update(value.to_string().as_bytes());
+    hasher.finalize().into()
+}
+
+/// `SHA-256(0x01 || left || right)`
+fn node(left: &[u8; BYTES], right: &[u8; BYTES]) -> [u8; BYTES] {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_record() {
+        let original =
+            json!({"time": "t1", "user": {"ip": "<hash>", "name": "alice"}, "status": 200});
+        let root = record(&original);
+        assert_eq!(root, record(&original.clone()));
+
+        let changes = [
+            // edited value
+            json!({"time": "t1", "user": {"ip": "<hash>", "name": "bob"}, "status": 200}),
+            // same value as a string
+            json!({"time": "t1", "user": {"ip": "<hash>", "name": "alice"}, "status": "200"}),
+            // reordered keys
+            json!({"user": {"ip": "<hash>", "name": "alice"}, "time": "t1", "status": 200}),
+            // removed column
+            json!({"time": "t1", "user": {"ip": "<hash>", "name": "alice"}}),
+            // added column
+            json!({"time": "t1", "user": {"ip": "<hash>", "name": "alice"}, "status": 200, "x": null}),
+            // renamed column
+            json!({"time": "t1", "user": {"ip": "<hash>", "nick": "alice"}, "status": 200}),
+        ];
+        for changed in changes {
+            assert_ne!(record(&changed), root, "{}", changed);
+        }
+        assert_ne!(record(&json!({})), record(&json!({"a": {}})));
+    }
+
+    #[test]
+    fn test_chain() {
+        let records: Vec<[u8; BYTES]> = ["a", "b", "c"]
+            .iter()
+            .map(|v| record(&json!({ "v": v })))
+            .collect();
+        let head = |log_id: &str, order: &[usize]| {
+            let mut chain = Chain::new(log_id);
+            for &i in order {
+                chain.push(&records[i], &[]);
+            }
+            (chain.head(), chain.count())
+        };
+
+        let (original, count) = head("log", &[0, 1, 2]);
+        assert_eq!(count, 3);
+        assert_eq!(head("log", &[0, 1, 2]).0, original);
+        for changed in [&[0, 2, 1][..], &[0, 1], &[0, 2], &[0, 1, 1, 2]] {
+            assert_ne!(head("log", changed).0, original, "{:?}", changed);
+        }
+        assert_ne!(head("other", &[0, 1, 2]).0, original);
+
+        // The hidden cells are part of the chain.
+        let hidden = |names: &[&str]| {
+            let names: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+            Chain::new("log").push(&records[0], &names)
+        };
+        assert_ne!(hidden(&["user"]), hidden(&[]));
+        assert_ne!(hidden(&["user", "ip"]), hidden(&["user"]));
+        assert_ne!(hidden(&["ab"]), hidden(&["a", "b"]));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::zk::Hash;
+use bellman::groth16::{Parameters, VerifyingKey};
+use bls12_381::Bls12;
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+/// Magic bytes at the head of a proving parameters file, followed by a
+/// version byte.
+const PARAMS_MAGIC: &[u8; 7] = b"ZK4LPRM";
+const PARAMS_VERSION: u8 = 3;
+/// Version written before hash modes could be chosen, with no hash name.
+/// Its parameters are all for `sha256d`.
+const PARAMS_VERSION_SHA256D: u8 = 2;
+
+/// Magic bytes at the head of a verifying key file, followed by a version
+/// byte.
+const KEY_MAGIC: &[u8; 7] = b"ZK4LKEY";
+const KEY_VERSION: u8 = 1;
+
+/// Writes the Groth16 parameters for the `hash` circuit of `blocks` blocks to
+/// `path`.
+///
+/// The file layout is
+/// `<magic><version><blocks: u32 LE><hash name length: u8><hash name><bellman parameters>`.
+pub fn write_params(
+    path: &str,
+    hash: Hash,
+    blocks: usize,
+    params: &Parameters<Bls12>,
+) -> io::Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    file.write_all(PARAMS_MAGIC)?;
+    file.write_all(&[PARAMS_VERSION])?;
+    file.write_all(&(blocks as u32).to_le_bytes())?;
+    file.write_all(&[hash.name().len() as u8])?;
+    file.write_all(hash.name().as_bytes())?;
+    params.write(&mut file)?;
+    file.flush()
+}
+
+/// Reads parameters written by `write_params` and returns them together with
+/// the hash and the number of blocks the circuit was generated for.
+pub fn read_params(path: &str) -> io::Result<(Hash, usize, Parameters<Bls12>)> {
+    let mut file = BufReader::new(fs::File::open(path)?);
+
+    let mut magic = [0; 7];
+    let mut version = [0; 1];
+    file.read_exact(&mut magic)?;
+    file.read_exact(&mut version)?;
+    if &magic != PARAMS_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a zk4log parameters file",
+        ));
+    }
+    if version[0] != PARAMS_VERSION && version[0] != PARAMS_VERSION_SHA256D {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "parameters file version {} is outdated, run `zk4log keygen` again",
+                version[0]
+            ),
+        ));
+    }
+
+    let mut blocks = [0; 4];
+    file.read_exact(&mut blocks)?;
+    let blocks = u32::from_le_bytes(blocks) as usize;
+
+    let hash = if version[0] == PARAMS_VERSION_SHA256D {
+        Hash::Sha256d
+    } else {
+        let mut len = [0; 1];
+        file.read_exact(&mut len)?;
+        let mut name = vec![0; len[0] as usize];
+        file.read_exact(&mut name)?;
+        let name = String::from_utf8_lossy(&name);
+        Hash::parse(&name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("parameters for unsupported hash {}", name),
+            )
+        })?
+    };
+
+    // The parameters are generated locally by `zk4log keygen`, so skip the
+    // (very slow) subgroup checks on every curve point.
+    let params = Parameters::read(&mut file, false)?;
+    Ok((hash, blocks, params))
+}
+
+/// Writes the verifying keys of one or more hashes to `path`, so that a log
+/// whose cells were hidden with several hashes is verified with one file.
+///
+/// The file layout is
+/// `<magic><version><count: u8>` followed by
+/// `<hash name length: u8><hash name><bellman verifying key>` for every key.
+pub fn write_vk(path: &str, keys: &[(Hash, &VerifyingKey<Bls12>)]) -> io::Result<()> {
+    let mut file = BufWriter::new(fs::File::create(path)?);
+    file.write_all(KEY_MAGIC)?;
+    file.write_all(&[KEY_VERSION, keys.len() as u8])?;
+    for (hash, vk) in keys {
+        file.write_all(&[hash.name().len() as u8])?;
+        file.write_all(hash.name().as_bytes())?;
+        vk.write(&mut file)?;
+    }
+    file.flush()
+}
+
+/// Reads the verifying keys written by `write_vk`. Key files written before
+/// they named their hash hold a single bare key, returned with no hash.
+pub fn read_vk(path: &str) -> io::Result<Vec<(Option<Hash>, VerifyingKey<Bls12>)>> {
+    let data = fs::read(path)?;
+    let Some(mut data) = data.strip_prefix(KEY_MAGIC) else {
+        return Ok(vec![(None, VerifyingKey::read(&data[..])?)]);
+    };
+
+    let mut header = [0; 2];
+    data.read_exact(&mut header)?;
+    if header[0] != KEY_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported key file version {}", header[0]),
+        ));
+    }
+    let mut keys = Vec::new();
+    for _ in 0..header[1] {
+        let mut len = [0; 1];
+        data.read_exact(&mut len)?;
+        let mut name = vec![0; len[0] as usize];
+        data.read_exact(&mut name)?;
+        let name = String::from_utf8_lossy(&name);
+        let hash = Hash::parse(&name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("key for unsupported hash {}", name),
+            )
+        })?;
+        keys.push((Some(hash), VerifyingKey::read(&mut data)?));
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TempDir;
+    use crate::zk;
+    use bellman::groth16;
+
+    #[test]
+    fn test_params_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let params_path = &dir.path("test.params");
+        let key_path = &dir.path("key.pub");
+
+        let (params, _pvk) = zk::setup(Hash::Blake2s, 1);
+        let (mimc, _pvk) = zk::setup(Hash::Mimc, 1);
+        write_params(params_path, Hash::Blake2s, 1, &params).unwrap();
+        write_vk(
+            key_path,
+            &[(Hash::Mimc, &mimc.vk), (Hash::Blake2s, &params.vk)],
+        )
+        .unwrap();
+
+        let (hash, blocks, params) = read_params(params_path).unwrap();
+        assert_eq!((hash, blocks), (Hash::Blake2s, 1));
+        let keys = read_vk(key_path).unwrap();
+        assert_eq!(
+            keys.iter().map(|(hash, _)| *hash).collect::<Vec<_>>(),
+            vec![Some(Hash::Mimc), Some(Hash::Blake2s)]
+        );
+        let pvk = groth16::prepare_verifying_key(&keys[1].1);
+
+        let m = b"\"192.168.0.1\"2rL9AkP0zS6E8yYX";
+        let binding = zk::Binding {
+            log_id: "test".to_string(),
+            record: 0,
+            column: "ip".to_string(),
+            commitment: [0; 32],
+        };
+        let proof = zk::prove(&params, hash, blocks, m, &binding);
+        assert!(zk::verify(&pvk, &hash.digest(m), &binding, &proof));
+        assert!(!zk::verify(
+            &pvk,
+            &Hash::Sha256d.digest(m),
+            &binding,
+            &proof
+        ));
+
+        fs::write(params_path, b"not params").unwrap();
+        assert!(read_params(params_path).is_err());
+
+        // A bare key, as written before keys named their hash.
+        let mut bare = Vec::new();
+        params.vk.write(&mut bare).unwrap();
+        fs::write(key_path, bare).unwrap();
+        let keys = read_vk(key_path).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].0, None);
+    }
+}
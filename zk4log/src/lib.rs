@@ -1,5 +1,22 @@
+mod access;
+mod bundle;
+mod columns;
+mod commitment;
+mod keys;
+mod mimc;
 mod nu;
+mod opening;
+mod otlp;
+mod path;
+mod policy;
 mod progress_bar;
+mod proof_file;
+mod records;
+mod scan;
+mod signing;
+mod syslog;
+mod vault;
+mod zeek;
 mod zk;
 mod zk4log;
 
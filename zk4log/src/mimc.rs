@@ -0,0 +1,163 @@
+//! MiMC over the BLS12-381 scalar field, a hash made for circuits.
+//!
+//! SHA-256 and BLAKE2s work on bits, so their circuits take tens of thousands
+//! of constraints per block. MiMC works on field elements: one round of its
+//! Feistel permutation, `(xL, xR) -> (xR + (xL + c)^3, xL)`, takes two
+//! constraints. With 322 rounds, as in bellman's MiMC example for this field,
+//! a whole permutation takes 644.
+//!
+//! Preimages are hashed with a sponge. The state starts as `(0, length in
+//! bytes)`, every chunk of 31 bytes (the last one zero padded, at least one
+//! chunk) is added to `xL` as a little-endian number and the state is permuted.
+//! The hash is the final `xL` in little-endian bytes.
+
+use bellman::{gadgets::num::AllocatedNum, ConstraintSystem, SynthesisError};
+use ff::{Field, PrimeField};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Rounds of the permutation.
+pub const ROUNDS: usize = 322;
+/// Bytes absorbed per permutation, the most that always fit in a field element.
+pub const CHUNK_BYTES: usize = 31;
+
+/// Round constants: the first of `SHA-256("zk4log/mimc" || round (u32 BE) ||
+/// attempt (u32 BE))` that is a field element, for every round.
+pub fn constants<Scalar: PrimeField>() -> Vec<Scalar> {
+    (0..ROUNDS as u32)
+        .map(|round| {
+            (0u32..)
+                .find_map(|attempt| {
+                    let mut hasher = Sha256::new();
+                    hasher.update(b"zk4log/mimc");
+                    hasher.update(round.to_be_bytes());
+                    hasher.update(attempt.to_be_bytes());
+                    let mut repr = Scalar::Repr::default();
+                    repr.as_mut().copy_from_slice(&hasher.finalize());
+                    Option::from(Scalar::from_repr(repr))
+                })
+                .expect("a field element is found")
+        })
+        .collect()
+}
+
+/// A chunk of up to `CHUNK_BYTES` bytes as a little-endian number.
+pub fn chunk<Scalar: PrimeField>(bytes: &[u8]) -> Scalar {
+    assert!(bytes.len() <= CHUNK_BYTES);
+    bytes.iter().rev().fold(Scalar::ZERO, |acc, &byte| {
+        acc * Scalar::from(256) + Scalar::from(byte as u64)
+    })
+}
+
+/// The permutation of the state `(xl, xr)`.
+pub fn permute<Scalar: PrimeField>(
+    mut xl: Scalar,
+    mut xr: Scalar,
+    constants: &[Scalar],
+) -> (Scalar, Scalar) {
+    for c in constants {
+        let t = xl + c;
+        (xl, xr) = (xr + t.square() * t, xl);
+    }
+    (xl, xr)
+}
+
+/// MiMC hash of `preimage`.
+pub fn hash(preimage: &[u8]) -> [u8; 32] {
+    static CONSTANTS: OnceLock<Vec<bls12_381::Scalar>> = OnceLock::new();
+    let constants = CONSTANTS.get_or_init(constants);
+
+    let mut xl = bls12_381::Scalar::ZERO;
+    let mut xr = bls12_381::Scalar::from(preimage.len() as u64);
+    let chunks: Vec<&[u8]> = match preimage.len() {
+        0 => vec![&[]],
+        _ => preimage.chunks(CHUNK_BYTES).collect(),
+    };
+    for bytes in chunks {
+        (xl, xr) = permute(xl + chunk::<bls12_381::Scalar>(bytes), xr, constants);
+    }
+    xl.to_repr()
+}
+
+/// The permutation of the state `(xl, xr)` in a circuit.
+pub fn permute_gadget<Scalar, CS>(
+    mut cs: CS,
+    xl: &AllocatedNum<Scalar>,
+    xr: &AllocatedNum<Scalar>,
+    constants: &[Scalar],
+) -> Result<(AllocatedNum<Scalar>, AllocatedNum<Scalar>), SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    let (mut xl, mut xr) = (xl.clone(), xr.clone());
+    for (i, c) in constants.iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("round {}", i));
+
+        // tmp = (xL + c)^2
+        let tmp = AllocatedNum::alloc(cs.namespace(|| "tmp"), || {
+            let t = xl.get_value().ok_or(SynthesisError::AssignmentMissing)? + c;
+            Ok(t.square())
+        })?;
+        cs.enforce(
+            || "tmp = (xL + c)^2",
+            |lc| lc + xl.get_variable() + (*c, CS::one()),
+            |lc| lc + xl.get_variable() + (*c, CS::one()),
+            |lc| lc + tmp.get_variable(),
+        );
+
+        // new xL = xR + (xL + c)^3
+        let new_xl = AllocatedNum::alloc(cs.namespace(|| "new xL"), || {
+            let t = xl.get_value().ok_or(SynthesisError::AssignmentMissing)? + c;
+            let tmp = tmp.get_value().ok_or(SynthesisError::AssignmentMissing)?;
+            Ok(xr.get_value().ok_or(SynthesisError::AssignmentMissing)? + tmp * t)
+        })?;
+        cs.enforce(
+            || "new xL = xR + (xL + c)^3",
+            |lc| lc + tmp.get_variable(),
+            |lc| lc + xl.get_variable() + (*c, CS::one()),
+            |lc| lc + new_xl.get_variable() - xr.get_variable(),
+        );
+
+        (xl, xr) = (new_xl, xl);
+    }
+    Ok((xl, xr))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bellman::gadgets::test::TestConstraintSystem;
+    use bls12_381::Scalar;
+
+    #[test]
+    fn test_hash() {
+        let constants = constants::<Scalar>();
+        assert_eq!(constants.len(), ROUNDS);
+        assert_ne!(constants[0], constants[1]);
+        assert_eq!(chunk::<Scalar>(&[1, 2]), Scalar::from(0x0201));
+
+        // Zero padding and lengths must not collide.
+        let preimages: [&[u8]; 6] = [b"", b"\0", b"a", b"a\0", &[b'a'; 31], &[b'a'; 32]];
+        for (i, a) in preimages.iter().enumerate() {
+            assert_eq!(hash(a), hash(a));
+            for b in &preimages[i + 1..] {
+                assert_ne!(hash(a), hash(b), "{:?} {:?}", a, b);
+            }
+        }
+
+        // The gadget computes the same permutation.
+        let (xl, xr) = (Scalar::from(3), Scalar::from(5));
+        let mut cs = TestConstraintSystem::<Scalar>::new();
+        let xl_num = AllocatedNum::alloc(cs.namespace(|| "xl"), || Ok(xl)).unwrap();
+        let xr_num = AllocatedNum::alloc(cs.namespace(|| "xr"), || Ok(xr)).unwrap();
+        let (out_l, out_r) =
+            permute_gadget(cs.namespace(|| "permute"), &xl_num, &xr_num, &constants).unwrap();
+        assert!(cs.is_satisfied());
+        assert_eq!(cs.num_constraints(), 2 * ROUNDS);
+        assert_eq!(
+            (out_l.get_value().unwrap(), out_r.get_value().unwrap()),
+            permute(xl, xr, &constants)
+        );
+    }
+}
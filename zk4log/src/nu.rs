@@ -5,23 +5,244 @@ use nu_protocol::{PluginSignature, SyntaxShape, Value};
 impl Plugin for Zk4log {
     fn signature(&self) -> Vec<PluginSignature> {
         vec![
-            PluginSignature::build("zk4log hide")
+            log_flags(PluginSignature::build("zk4log hide"))
                 .usage("Hide log and generate proof")
-                .required("path", SyntaxShape::String, "json file path"),
-            PluginSignature::build("zk4log verify")
+                .optional(
+                    "path",
+                    SyntaxShape::String,
+                    "log file path (default: the table from the pipeline)",
+                )
+                .named(
+                    "columns",
+                    SyntaxShape::String,
+                    "comma separated columns or glob patterns to hide (default: ask)",
+                    Some('c'),
+                )
+                .named(
+                    "all-except",
+                    SyntaxShape::String,
+                    "hide all columns except these comma separated columns or glob patterns",
+                    Some('x'),
+                )
+                .named(
+                    "policy",
+                    SyntaxShape::String,
+                    "redaction policy file choosing the cells to hide",
+                    None,
+                )
+                .named(
+                    "output",
+                    SyntaxShape::String,
+                    "hidden log file name (default output.<extension of the log>)",
+                    Some('o'),
+                )
+                .named(
+                    "proof",
+                    SyntaxShape::String,
+                    "proof file name (default out.proof)",
+                    Some('p'),
+                )
+                .named(
+                    "bundle",
+                    SyntaxShape::String,
+                    "write the hidden log, proofs and verifying key into this bundle file instead",
+                    Some('b'),
+                )
+                .named(
+                    "sign-key",
+                    SyntaxShape::String,
+                    "sign the bundle with this Ed25519 key made by `zk4log keygen --signing`",
+                    None,
+                )
+                .named(
+                    "params",
+                    SyntaxShape::String,
+                    "proving parameters files made by `zk4log keygen`, comma-separated, one per hash",
+                    None,
+                )
+                .named(
+                    "hash",
+                    SyntaxShape::String,
+                    "hash of cells no policy rule gives one: \"sha256d\", \"sha256\", \"blake2s\" or \"mimc\" (default: the hash of the first --params, else sha256d)",
+                    None,
+                )
+                .named(
+                    "log-id",
+                    SyntaxShape::String,
+                    "ID of the log the proofs are bound to (default: random)",
+                    None,
+                )
+                .named(
+                    "salt-mode",
+                    SyntaxShape::String,
+                    "\"cell\" for a salt per hidden cell (default) or \"log\" for one salt per run",
+                    None,
+                )
+                .named(
+                    "vault",
+                    SyntaxShape::String,
+                    "salt vault file name (default <output>.vault)",
+                    None,
+                )
+                .named(
+                    "vault-recipient",
+                    SyntaxShape::String,
+                    "encrypt the salt vault to this age public key (age1...)",
+                    None,
+                )
+                .switch(
+                    "vault-passphrase",
+                    "encrypt the salt vault with a passphrase (ZK4LOG_VAULT_PASSPHRASE or prompt)",
+                    None,
+                ),
+            log_flags(PluginSignature::build("zk4log verify"))
                 .usage("Verify secret log with proof")
-                .required_named("json", SyntaxShape::String, "json file name", Some('j'))
-                .required_named("proof", SyntaxShape::String, "proof file name", Some('p'))
-                .required_named("key", SyntaxShape::String, "key file name", Some('k')),
-            PluginSignature::build("zk4log open")
-                .usage("Display log json file")
-                .required("path", SyntaxShape::String, "json file path"),
+                .optional(
+                    "bundle",
+                    SyntaxShape::String,
+                    "bundle made by `zk4log hide --bundle` (instead of --json, --proof and --key)",
+                )
+                .named(
+                    "json",
+                    SyntaxShape::String,
+                    "hidden log file name (default: the table from the pipeline)",
+                    Some('j'),
+                )
+                .named("proof", SyntaxShape::String, "proof file name", Some('p'))
+                .named(
+                    "key",
+                    SyntaxShape::String,
+                    "key file names, comma-separated (default: the keys in the bundle)",
+                    Some('k'),
+                )
+                .named(
+                    "policy",
+                    SyntaxShape::String,
+                    "check that the log was hidden with this redaction policy",
+                    None,
+                )
+                .named(
+                    "report-format",
+                    SyntaxShape::String,
+                    "\"table\" (default) or \"json\" to return the report as a JSON string",
+                    None,
+                )
+                .named(
+                    "trusted-signers",
+                    SyntaxShape::String,
+                    "directory of the public keys (*.pub) of trusted bundle signers",
+                    None,
+                ),
+            log_flags(PluginSignature::build("zk4log open"))
+                .usage("Display the selected columns of a log as a table")
+                .required("path", SyntaxShape::String, "log file path")
+                .named(
+                    "columns",
+                    SyntaxShape::String,
+                    "comma separated columns or glob patterns to open (default: ask)",
+                    Some('c'),
+                )
+                .named(
+                    "all-except",
+                    SyntaxShape::String,
+                    "open all columns except these comma separated columns or glob patterns",
+                    Some('x'),
+                ),
+            PluginSignature::build("zk4log keygen")
+                .usage("Generate proving parameters and verifying key, or a signing key pair")
+                .named(
+                    "hash",
+                    SyntaxShape::String,
+                    "\"sha256d\" (default), \"sha256\", \"blake2s\" or \"mimc\"",
+                    None,
+                )
+                .named(
+                    "blocks",
+                    SyntaxShape::Int,
+                    "hash blocks per value, i.e. values up to 64 * blocks - 9 bytes \
+                     (64 * blocks with blake2s, 31 * blocks with mimc, default 4)",
+                    Some('b'),
+                )
+                .named(
+                    "params",
+                    SyntaxShape::String,
+                    "proving parameters file name (default zk4log.params)",
+                    Some('p'),
+                )
+                .named(
+                    "key",
+                    SyntaxShape::String,
+                    "verifying key file name (default key.pub, signing.pub with --signing)",
+                    Some('k'),
+                )
+                .switch(
+                    "signing",
+                    "make an Ed25519 key pair for `zk4log hide --sign-key` instead",
+                    None,
+                ),
+            log_flags(PluginSignature::build("zk4log reveal"))
+                .usage("Disclose the original values of hidden cells")
+                .required("path", SyntaxShape::String, "original json file path")
+                .named(
+                    "vault",
+                    SyntaxShape::String,
+                    "salt vault file name (default output.<extension of the log>.vault)",
+                    Some('v'),
+                )
+                .named(
+                    "records",
+                    SyntaxShape::String,
+                    "comma separated record indices to reveal (default all)",
+                    Some('r'),
+                )
+                .named(
+                    "columns",
+                    SyntaxShape::String,
+                    "comma separated columns or glob patterns to reveal (default all)",
+                    Some('c'),
+                )
+                .named(
+                    "identity",
+                    SyntaxShape::String,
+                    "age identity file the vault is encrypted to (default: passphrase)",
+                    Some('i'),
+                )
+                .named(
+                    "output",
+                    SyntaxShape::String,
+                    "opening file name (default opening.json)",
+                    Some('o'),
+                ),
+            log_flags(PluginSignature::build("zk4log check-reveal"))
+                .usage("Check revealed values against the hidden log")
+                .required("path", SyntaxShape::String, "opening file path")
+                .named(
+                    "json",
+                    SyntaxShape::String,
+                    "hidden log file name (default output.<extension of the revealed log>)",
+                    Some('j'),
+                )
+                .named(
+                    "proof",
+                    SyntaxShape::String,
+                    "proof file of the hidden log, to check its log ID (default out.proof)",
+                    Some('p'),
+                ),
+            log_flags(PluginSignature::build("zk4log scan"))
+                .usage("Find columns holding personal data")
+                .required("path", SyntaxShape::String, "json file path")
+                .named(
+                    "sample",
+                    SyntaxShape::Int,
+                    "number of records to sample (default 1000)",
+                    Some('s'),
+                ),
             PluginSignature::build("zk4log")
                 .usage("Log Analysis Tool with ZKP")
                 .required(
                     "subcommand",
                     SyntaxShape::String,
-                    "\"hide\", \"open\" or \"verify\"",
+                    "\"hide\", \"open\", \"verify\", \"keygen\", \"reveal\", \"check-reveal\" or \"scan\"",
                 ),
         ]
     }
@@ -36,6 +257,10 @@ impl Plugin for Zk4log {
             "zk4log hide" => self.hide(call, input),
             "zk4log verify" => self.verify(call, input),
             "zk4log open" => self.open(call, input),
+            "zk4log keygen" => self.keygen(call, input),
+            "zk4log reveal" => self.reveal(call, input),
+            "zk4log check-reveal" => self.check_reveal(call, input),
+            "zk4log scan" => self.scan(call, input),
             "zk4log" => self.zk4log(call, input),
             _ => Err(LabeledError {
                 label: "Plugin call with wrong name".into(),
@@ -45,3 +270,27 @@ impl Plugin for Zk4log {
         }
     }
 }
+
+/// Adds the flags choosing how a log file is read, shared by every command
+/// reading one.
+fn log_flags(signature: PluginSignature) -> PluginSignature {
+    signature
+        .named(
+            "format",
+            SyntaxShape::String,
+            "log format, \"json\", \"ndjson\", \"csv\", \"tsv\", \"syslog\", \"access\", \"cloudtrail\", \"k8s-audit\", \"suricata\", \"zeek\" or \"otlp\" (default: detected from the file)",
+            Some('f'),
+        )
+        .named(
+            "delimiter",
+            SyntaxShape::String,
+            "delimiter of CSV logs (default \",\", tab for TSV)",
+            Some('d'),
+        )
+        .named(
+            "log-format",
+            SyntaxShape::String,
+            "nginx log_format of access logs (default: combined)",
+            None,
+        )
+}
@@ -0,0 +1,73 @@
+//! Opening files made by `zk4log reveal`.
+//!
+//! An opening discloses the original value and the salt of some hidden cells,
+//! so that an auditor can recompute the hash of each cell and compare it with
+//! the hidden log without access to the salt vault.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, io};
+
+/// Value of the `format` field, identifying zk4log opening files.
+pub const FORMAT: &str = "zk4log-opening";
+/// Current version of the opening file format.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Opening {
+    pub format: String,
+    pub version: u32,
+    /// Hash the hidden cells were made with, e.g. `sha256d`, unless the cell
+    /// names another.
+    pub hash: String,
+    /// ID of the hidden log the cells belong to.
+    pub log_id: String,
+    /// Extension of the hidden log as written by `hide` (see
+    /// `Format::extension`), so that `check-reveal` finds it by default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<String>,
+    pub cells: Vec<OpenedCell>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenedCell {
+    pub record: usize,
+    pub column: String,
+    /// The original value of the cell.
+    pub value: serde_json::Value,
+    pub salt: String,
+    /// Hash of the cell, if it differs from the opening's (see
+    /// `vault::SaltEntry::hash`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Opening {
+    pub fn new(hash: &str, log_id: String, extension: &str) -> Opening {
+        Opening {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            hash: hash.to_string(),
+            log_id,
+            extension: Some(extension.to_string()),
+            cells: Vec::new(),
+        }
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn read(path: &str) -> io::Result<Opening> {
+        let opening: Opening = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if opening.format != FORMAT || opening.version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported opening format {} version {}",
+                    opening.format, opening.version
+                ),
+            ));
+        }
+        Ok(opening)
+    }
+}
@@ -0,0 +1,165 @@
+//! Reader for OpenTelemetry logs in the OTLP/JSON encoding.
+//!
+//! An OTLP/JSON document (`ExportLogsServiceRequest`) groups log records by
+//! resource and instrumentation scope. Every log record becomes one record
+//! with its own fields (`timeUnixNano`, `severityText`, `body`, ...), its
+//! attributes as an object of keys to values, and the attributes of its
+//! resource and scope under `resource` and `scope`. `AnyValue`s such as
+//! `{"stringValue": "..."}` are replaced by the plain values.
+
+use serde_json::{Map, Value};
+
+/// Returns the log records of an OTLP/JSON document.
+pub fn records(document: Value) -> Result<Vec<Value>, String> {
+    let resource_logs = document
+        .get("resourceLogs")
+        .and_then(Value::as_array)
+        .ok_or("expected an OTLP/JSON document with resourceLogs")?;
+
+    let mut records = Vec::new();
+    for resource_log in resource_logs {
+        let resource = attributes(resource_log.pointer("/resource/attributes"))?;
+        for scope_log in array(resource_log, "scopeLogs")? {
+            let mut scope = Map::new();
+            if let Some(Value::Object(s)) = scope_log.get("scope") {
+                for (key, value) in s {
+                    let value = match key.as_str() {
+                        "attributes" => Value::Object(attributes(Some(value))?),
+                        _ => value.clone(),
+                    };
+                    scope.insert(key.clone(), value);
+                }
+            }
+
+            for log_record in array(scope_log, "logRecords")? {
+                let Value::Object(fields) = log_record else {
+                    return Err("a log record must be an object".to_string());
+                };
+                let mut record = Map::new();
+                for (key, value) in fields {
+                    let value = match key.as_str() {
+                        "body" => any_value(value)?,
+                        "attributes" => Value::Object(attributes(Some(value))?),
+                        _ => value.clone(),
+                    };
+                    record.insert(key.clone(), value);
+                }
+                if !resource.is_empty() {
+                    record.insert("resource".to_string(), Value::Object(resource.clone()));
+                }
+                if !scope.is_empty() {
+                    record.insert("scope".to_string(), Value::Object(scope.clone()));
+                }
+                records.push(Value::Object(record));
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> Result<&'a [Value], String> {
+    match value.get(key) {
+        Some(Value::Array(array)) => Ok(array),
+        None => Ok(&[]),
+        Some(_) => Err(format!("{} must be an array", key)),
+    }
+}
+
+/// Turns a list of `KeyValue`s into an object.
+fn attributes(list: Option<&Value>) -> Result<Map<String, Value>, String> {
+    let Some(list) = list else {
+        return Ok(Map::new());
+    };
+    let list = list.as_array().ok_or("attributes must be an array")?;
+    list.iter()
+        .map(|kv| {
+            let key = kv
+                .get("key")
+                .and_then(Value::as_str)
+                .ok_or("an attribute has no key")?;
+            let value = kv.get("value").map_or(Ok(Value::Null), any_value)?;
+            Ok((key.to_string(), value))
+        })
+        .collect()
+}
+
+fn any_value(value: &Value) -> Result<Value, String> {
+    let Value::Object(map) = value else {
+        return Err("invalid AnyValue".to_string());
+    };
+    let Some((kind, inner)) = map.iter().next() else {
+        return Ok(Value::Null);
+    };
+    Ok(match kind.as_str() {
+        // 64 bit integers are encoded as strings
+        "intValue" => match inner.as_str().and_then(|s| s.parse::<i64>().ok()) {
+            Some(n) => Value::from(n),
+            None => inner.clone(),
+        },
+        "stringValue" | "boolValue" | "doubleValue" | "bytesValue" => inner.clone(),
+        "arrayValue" => Value::Array(
+            array(inner, "values")?
+                .iter()
+                .map(any_value)
+                .collect::<Result<_, _>>()?,
+        ),
+        "kvlistValue" => Value::Object(attributes(inner.get("values"))?),
+        _ => return Err(format!("unknown AnyValue {}", kind)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_otlp() {
+        let document = json!({
+            "resourceLogs": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "checkout"}},
+                ]},
+                "scopeLogs": [{
+                    "scope": {"name": "app", "version": "1.0"},
+                    "logRecords": [{
+                        "timeUnixNano": "1544712660300000000",
+                        "severityNumber": 9,
+                        "severityText": "INFO",
+                        "body": {"stringValue": "user logged in"},
+                        "attributes": [
+                            {"key": "client.address", "value": {"stringValue": "192.0.2.1"}},
+                            {"key": "retries", "value": {"intValue": "3"}},
+                            {"key": "tags", "value": {"arrayValue": {"values": [{"boolValue": true}]}}},
+                            {"key": "user", "value": {"kvlistValue": {"values": [
+                                {"key": "email", "value": {"stringValue": "a@example.com"}},
+                            ]}}},
+                        ],
+                        "traceId": "5b8efff798038103d269b633813fc60c",
+                    }],
+                }],
+            }],
+        });
+        assert_eq!(
+            records(document).unwrap(),
+            vec![json!({
+                "timeUnixNano": "1544712660300000000",
+                "severityNumber": 9,
+                "severityText": "INFO",
+                "body": "user logged in",
+                "attributes": {
+                    "client.address": "192.0.2.1",
+                    "retries": 3,
+                    "tags": [true],
+                    "user": {"email": "a@example.com"},
+                },
+                "traceId": "5b8efff798038103d269b633813fc60c",
+                "resource": {"service.name": "checkout"},
+                "scope": {"name": "app", "version": "1.0"},
+            })]
+        );
+
+        assert!(records(json!({"Records": []})).is_err());
+        assert!(records(json!({"resourceLogs": [{"scopeLogs": {}}]})).is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! Paths of cells inside nested JSON records.
+//!
+//! A cell of a record is named by its path: a top-level key as it is (`user`),
+//! a nested value with dots (`user.ip`, `headers.0.value`), or as a JSON
+//! Pointer (`/headers/0/value`, `/a.b`) when a key contains a dot, starts with
+//! a slash or is empty. Every name therefore points at exactly one cell: the
+//! top-level key `a.b` is `/a.b`, while `a.b` is the key `b` inside `a`. The
+//! paths are used as column names everywhere, including the proof file.
+
+use serde_json::Value;
+
+/// Splits a path into its keys and array indices.
+pub fn parse(path: &str) -> Vec<String> {
+    match path.strip_prefix('/') {
+        // RFC 6901: "~1" is "/" and "~0" is "~"
+        Some(pointer) => pointer
+            .split('/')
+            .map(|s| s.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+        None => path.split('.').map(str::to_string).collect(),
+    }
+}
+
+/// Names a cell by the keys and array indices leading to it.
+pub fn name(segments: &[String]) -> String {
+    let plain =
+        !segments[0].starts_with('/') && segments.iter().all(|s| !s.is_empty() && !s.contains('.'));
+    if plain {
+        segments.join(".")
+    } else {
+        segments
+            .iter()
+            .map(|s| format!("/{}", s.replace('~', "~0").replace('/', "~1")))
+            .collect()
+    }
+}
+
+/// Turns a path given by the user (dotted or JSON Pointer) into the name used
+/// for the cell.
+pub fn canonical(path: &str) -> String {
+    if path.starts_with('/') {
+        name(&parse(path))
+    } else {
+        path.to_string()
+    }
+}
+
+/// Returns every leaf cell of `record` with its name, in document order.
+/// Values that are neither objects nor arrays, as well as empty objects and
+/// arrays, are leaves.
+pub fn leaves(record: &Value) -> Vec<(String, &Value)> {
+    fn walk<'a>(value: &'a Value, segments: &mut Vec<String>, out: &mut Vec<(String, &'a Value)>) {
+        let children: Vec<(String, &Value)> = match value {
+            Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), v)).collect(),
+            Value::Array(array) => array
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (i.to_string(), v))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if children.is_empty() {
+            if !segments.is_empty() {
+                out.push((name(segments), value));
+            }
+            return;
+        }
+        for (segment, child) in children {
+            segments.push(segment);
+            walk(child, segments, out);
+            segments.pop();
+        }
+    }
+
+    let mut out = Vec::new();
+    walk(record, &mut Vec::new(), &mut out);
+    out
+}
+
+/// Finds the cell named `path` in `record`.
+pub fn get<'a>(record: &'a Value, path: &str) -> Option<&'a Value> {
+    parse(path)
+        .iter()
+        .try_fold(record, |value, segment| match value {
+            Value::Object(map) => map.get(segment),
+            Value::Array(array) => array.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+/// Finds the cell named `path` in `record` for writing.
+pub fn get_mut<'a>(record: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    parse(path)
+        .iter()
+        .try_fold(record, |value, segment| match value {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(array) => array.get_mut(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_paths() {
+        let mut record = json!({
+            "time": "t1",
+            "user": {"name": "alice", "ip": "10.0.0.1"},
+            "headers": [{"name": "Host", "value": "example.com"}],
+            "a.b": {"c": 1},
+            "a": {"b": 2},
+            "x.y": 3,
+            "": 4,
+            "tags": [],
+        });
+
+        let names: Vec<String> = leaves(&record).into_iter().map(|(n, _)| n).collect();
+        assert_eq!(
+            names,
+            vec![
+                "time",
+                "user.name",
+                "user.ip",
+                "headers.0.name",
+                "headers.0.value",
+                "/a.b/c",
+                "a.b",
+                "/x.y",
+                "/",
+                "tags",
+            ]
+        );
+
+        assert_eq!(get(&record, "user.ip"), Some(&json!("10.0.0.1")));
+        assert_eq!(
+            get(&record, "/headers/0/value"),
+            Some(&json!("example.com"))
+        );
+        assert_eq!(get(&record, "/a.b/c"), Some(&json!(1)));
+        // A dotted top-level key and a nested key never share a name.
+        assert_eq!(get(&record, "a.b"), Some(&json!(2)));
+        assert_eq!(get(&record, "/a.b"), Some(&json!({"c": 1})));
+        assert_eq!(get(&record, "/x.y"), Some(&json!(3)));
+        assert_eq!(get(&record, "x.y"), None);
+        assert_eq!(get(&record, "/"), Some(&json!(4)));
+        assert_eq!(get(&record, "headers.1.value"), None);
+        assert_eq!(get(&record, "time.x"), None);
+        assert_eq!(canonical("/headers/0/value"), "headers.0.value");
+        assert_eq!(canonical("/a~1b"), "a/b");
+
+        *get_mut(&mut record, "headers.0.value").unwrap() = json!("hidden");
+        assert_eq!(record["headers"][0]["value"], json!("hidden"));
+        *get_mut(&mut record, "/x.y").unwrap() = json!("hidden");
+        assert_eq!(record["x.y"], json!("hidden"));
+        assert!(get_mut(&mut record, "x.y").is_none());
+    }
+}
@@ -0,0 +1,205 @@
+//! Redaction policies for `zk4log hide --policy`.
+//!
+//! A policy is a TOML file with a list of rules. A cell is hidden when a rule
+//! matches both its column and its value; a rule that leaves out one of the two
+//! matches any column or any value.
+//!
+//! ```toml
+//! [[rule]]
+//! field = "user"                 # exact column name
+//!
+//! [[rule]]
+//! field_glob = "src_*"           # glob pattern
+//!
+//! [[rule]]
+//! field_regex = "^(ip|addr)$"    # regular expression
+//! hash = "sha256d"
+//!
+//! [[rule]]
+//! value = '^[^@\s]+@[^@\s]+$'    # any column holding an email address
+//! ```
+//!
+//! `hash` sets the hash mode of the cells a rule hides; each cell is proven
+//! with the circuit of its own hash, so `hide` needs parameters for every hash
+//! the policy uses. When several rules match a cell, the first one wins.
+
+use crate::zk;
+use glob::Pattern;
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{fs, io};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    field: Option<String>,
+    field_glob: Option<String>,
+    field_regex: Option<String>,
+    value: Option<String>,
+    hash: Option<String>,
+}
+
+enum FieldMatcher {
+    Any,
+    Exact(String),
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+pub struct Rule {
+    field: FieldMatcher,
+    value: Option<Regex>,
+    /// Hash mode of the cells hidden by this rule, if the rule sets one.
+    /// Otherwise they are hidden with the `--hash` of the run.
+    pub hash: Option<zk::Hash>,
+}
+
+pub struct Policy {
+    pub rules: Vec<Rule>,
+    /// Hex SHA-256 of the policy file, recorded in the proof file header.
+    pub hash: String,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Rule {
+    fn parse(i: usize, rule: RuleFile) -> Result<Rule, String> {
+        let regex = |r: &str| Regex::new(r).map_err(|e| format!("rule {}: {}", i + 1, e));
+        let field = match (rule.field, rule.field_glob, rule.field_regex) {
+            (None, None, None) => FieldMatcher::Any,
+            (Some(field), None, None) => FieldMatcher::Exact(field),
+            (None, Some(glob), None) => FieldMatcher::Glob(
+                Pattern::new(&glob).map_err(|e| format!("rule {}: {}", i + 1, e))?,
+            ),
+            (None, None, Some(r)) => FieldMatcher::Regex(regex(&r)?),
+            _ => {
+                return Err(format!(
+                    "rule {}: use only one of field, field_glob and field_regex",
+                    i + 1
+                ))
+            }
+        };
+        if matches!(field, FieldMatcher::Any) && rule.value.is_none() {
+            return Err(format!("rule {}: matches every cell", i + 1));
+        }
+        let value = rule.value.as_deref().map(regex).transpose()?;
+
+        let hash = rule
+            .hash
+            .map(|hash| {
+                zk::Hash::parse(&hash).ok_or_else(|| {
+                    format!(
+                        "rule {}: unsupported hash mode {} (supported: {})",
+                        i + 1,
+                        hash,
+                        zk::Hash::NAMES
+                    )
+                })
+            })
+            .transpose()?;
+
+        Ok(Rule { field, value, hash })
+    }
+
+    fn matches(&self, column: &str, value: &serde_json::Value) -> bool {
+        let field = match &self.field {
+            FieldMatcher::Any => true,
+            FieldMatcher::Exact(field) => field == column,
+            FieldMatcher::Glob(pattern) => pattern.matches(column),
+            FieldMatcher::Regex(regex) => regex.is_match(column),
+        };
+        // Strings are matched as they are, other values as JSON text.
+        field
+            && self.value.as_ref().is_none_or(|regex| match value {
+                serde_json::Value::String(s) => regex.is_match(s),
+                _ => regex.is_match(&value.to_string()),
+            })
+    }
+}
+
+impl Policy {
+    pub fn parse(text: &str) -> Result<Policy, String> {
+        let file: PolicyFile = toml::from_str(text).map_err(|e| e.to_string())?;
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(i, rule)| Rule::parse(i, rule))
+            .collect::<Result<Vec<_>, _>>()?;
+        if rules.is_empty() {
+            return Err("the policy has no rules".to_string());
+        }
+
+        Ok(Policy {
+            rules,
+            hash: hex::encode(Sha256::digest(text.as_bytes())),
+        })
+    }
+
+    pub fn read(path: &str) -> io::Result<Policy> {
+        Policy::parse(&fs::read_to_string(path)?).map_err(invalid_data)
+    }
+
+    /// Returns the first rule hiding the cell, or `None` if it stays in clear
+    /// text.
+    pub fn rule_for(&self, column: &str, value: &serde_json::Value) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(column, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::parse(
+            r#"
+            [[rule]]
+            field = "user"
+
+            [[rule]]
+            field_glob = "src_*"
+
+            [[rule]]
+            field_regex = "^(ip|addr)$"
+            value = '^10\.'
+
+            [[rule]]
+            value = '^[^@\s]+@[^@\s]+$'
+            "#,
+        )
+        .unwrap();
+
+        let hidden = |column, value| policy.rule_for(column, &value).is_some();
+        assert!(hidden("user", json!("alice")));
+        assert!(!hidden("username", json!("alice")));
+        assert!(hidden("src_port", json!(443)));
+        assert!(hidden("ip", json!("10.0.0.1")));
+        assert!(!hidden("ip", json!("192.168.0.1")));
+        assert!(hidden("note", json!("alice@example.com")));
+        assert!(!hidden("note", json!("hello")));
+        assert_eq!(policy.rule_for("user", &json!(1)).unwrap().hash, None);
+        assert_eq!(policy.hash.len(), 64);
+
+        assert!(Policy::parse("").is_err());
+        assert!(Policy::parse("[[rule]]\nhash = \"sha256d\"").is_err());
+        assert!(Policy::parse("[[rule]]\nfield = \"a\"\nfield_glob = \"b\"").is_err());
+        assert!(Policy::parse("[[rule]]\nfield = \"a\"\nhash = \"md5\"").is_err());
+        let policy = Policy::parse("[[rule]]\nfield = \"a\"\nhash = \"blake2s\"").unwrap();
+        assert_eq!(policy.rules[0].hash, Some(zk::Hash::Blake2s));
+        assert!(Policy::parse("[[rule]]\nfeild = \"a\"").is_err());
+        assert!(Policy::parse("[[rule]]\nvalue = \"(\"").is_err());
+    }
+}
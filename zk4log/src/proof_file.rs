@@ -0,0 +1,340 @@
+//! The proof file written by `zk4log hide`.
+//!
+//! A proof file is a sequence of JSON lines: a header describing how the
+//! proofs were made, the commitment of every record (see `commitment`) with
+//! the cells hidden in it, one line per proof and an end marker holding the
+//! number of proofs and records, so that a truncated file can be told apart
+//! from a complete one. The header holds the commitment of the whole log,
+//! which every proof is bound to. Lines are written in the order of their
+//! records, so that the file can be read line by line alongside the log.
+//!
+//! Cells can be hidden with different hashes (see `policy`), so every proof
+//! names its hash and the header lists the circuit of each hash.
+//!
+//! ```text
+//! {"type":"header","format":"zk4log-proof","version":6,"circuits":{"mimc":"zk4log/mimc/v2/2","sha256d":"zk4log/sha256d/v2/4"},"log_id":"...","log":"<hex>","records":1}
+//! {"type":"record","record":0,"commitment":"<hex>","hidden":["ip","user"]}
+//! {"type":"proof","record":0,"column":"ip","hash_mode":"mimc","hash":"<hex>","proof":"<base64>"}
+//! {"type":"proof","record":0,"column":"user","hash_mode":"sha256d","hash":"<hex>","proof":"<base64>"}
+//! {"type":"end","count":2,"records":1}
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+};
+
+/// Value of the `format` field, identifying zk4log proof files.
+pub const FORMAT: &str = "zk4log-proof";
+/// Current version of the proof file format.
+pub const VERSION: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub format: String,
+    pub version: u32,
+    /// Circuit the proofs were made for (see `zk::Hash::circuit_id`), by the
+    /// name of the hash the cells were hidden with, e.g. `sha256d`.
+    pub circuits: BTreeMap<String, String>,
+    /// Identifies the hidden log. Every proof is bound to it.
+    pub log_id: String,
+    /// Hex encoded head of the `commitment::Chain` over all records of the
+    /// hidden log. Every proof is bound to it as well, so no record can be
+    /// changed, added or removed without the proofs failing.
+    pub log: String,
+    /// Number of records in the hidden log.
+    pub records: usize,
+    /// SHA-256 of the redaction policy the log was hidden with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Columns hidden in at least one record.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub columns: Vec<String>,
+}
+
+impl Header {
+    pub fn new(circuits: BTreeMap<String, String>, log_id: String) -> Header {
+        Header {
+            format: FORMAT.to_string(),
+            version: VERSION,
+            circuits,
+            log_id,
+            log: String::new(),
+            records: 0,
+            policy: None,
+            columns: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Index of the record in the hidden log.
+    pub record: usize,
+    /// Column of the hidden cell.
+    pub column: String,
+    /// Name of the hash the cell was hidden with. `verify` checks the proof
+    /// with the key of this hash.
+    pub hash_mode: String,
+    /// Hash written to the cell, so that `verify` can tell a changed cell
+    /// from a bad proof.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// The Groth16 proof, as written by `Proof::write`.
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub proof: Vec<u8>,
+}
+
+/// Commitment to a whole record of the hidden log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordCommitment {
+    pub record: usize,
+    /// Hex encoded `commitment::record` of the hidden record.
+    pub commitment: String,
+    /// Cells hidden in the record, each of which must have a proof.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hidden: Vec<String>,
+}
+
+/// A line of a proof file after the header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Item {
+    Record(RecordCommitment),
+    Proof(Entry),
+}
+
+impl Item {
+    /// Index of the record the line belongs to.
+    pub fn record(&self) -> usize {
+        match self {
+            Item::Record(record) => record.record,
+            Item::Proof(entry) => entry.record,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Line {
+    Header(Header),
+    Record(RecordCommitment),
+    Proof(Entry),
+    End { count: usize, records: usize },
+}
+
+fn to_base64<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    STANDARD.decode(s).map_err(serde::de::Error::custom)
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writes a proof file entry by entry. `finish` must be called once all
+/// entries are written, otherwise the file is read back as truncated.
+pub struct ProofWriter {
+    file: BufWriter<fs::File>,
+    count: usize,
+    records: usize,
+}
+
+impl ProofWriter {
+    pub fn create(path: &str, header: Header) -> io::Result<ProofWriter> {
+        let mut writer = ProofWriter {
+            file: BufWriter::new(fs::File::create(path)?),
+            count: 0,
+            records: 0,
+        };
+        writer.write_line(&Line::Header(header))?;
+        Ok(writer)
+    }
+
+    pub fn write(&mut self, entry: Entry) -> io::Result<()> {
+        self.count += 1;
+        self.write_line(&Line::Proof(entry))
+    }
+
+    pub fn write_record(&mut self, record: RecordCommitment) -> io::Result<()> {
+        self.records += 1;
+        self.write_line(&Line::Record(record))
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        let (count, records) = (self.count, self.records);
+        self.write_line(&Line::End { count, records })?;
+        self.file.flush()
+    }
+
+    fn write_line(&mut self, line: &Line) -> io::Result<()> {
+        serde_json::to_writer(&mut self.file, line)?;
+        self.file.write_all(b"\n")
+    }
+}
+
+/// Reads a proof file line by line, so that proofs can be checked against
+/// the log as they are read. Files that are malformed, truncated or of an
+/// unknown format or version are rejected: `open` checks the header and the
+/// iterator fails on a bad line or a missing or wrong end marker.
+pub struct Reader {
+    lines: io::Lines<BufReader<fs::File>>,
+    line: usize,
+    pub header: Header,
+    count: usize,
+    records: usize,
+    done: bool,
+}
+
+impl Reader {
+    pub fn open(path: &str) -> io::Result<Reader> {
+        let mut lines = BufReader::new(fs::File::open(path)?).lines();
+        let line = lines
+            .next()
+            .ok_or_else(|| invalid_data("empty proof file".to_string()))??;
+        let header = match serde_json::from_str(&line) {
+            Ok(Line::Header(header)) => header,
+            Ok(_) => return Err(invalid_data("missing proof file header".to_string())),
+            Err(e) => return Err(invalid_data(format!("line 1: {}", e))),
+        };
+        if header.format != FORMAT {
+            return Err(invalid_data(format!("not a {} file", FORMAT)));
+        }
+        if header.version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported proof file version {}",
+                header.version
+            )));
+        }
+        Ok(Reader {
+            lines,
+            line: 1,
+            header,
+            count: 0,
+            records: 0,
+            done: false,
+        })
+    }
+
+    fn next_item(&mut self) -> io::Result<Option<Item>> {
+        let Some(line) = self.lines.next().transpose()? else {
+            return Err(invalid_data("proof file is truncated".to_string()));
+        };
+        self.line += 1;
+        let line: Line = serde_json::from_str(&line)
+            .map_err(|e| invalid_data(format!("line {}: {}", self.line, e)))?;
+        match line {
+            Line::Header(_) => Err(invalid_data(format!(
+                "line {}: duplicate header",
+                self.line
+            ))),
+            Line::Record(record) => {
+                self.records += 1;
+                Ok(Some(Item::Record(record)))
+            }
+            Line::Proof(entry) => {
+                self.count += 1;
+                Ok(Some(Item::Proof(entry)))
+            }
+            Line::End { count, .. } if count != self.count => Err(invalid_data(format!(
+                "proof file holds {} proofs but should hold {}",
+                self.count, count
+            ))),
+            Line::End { records, .. } if records != self.records => Err(invalid_data(format!(
+                "proof file holds {} record commitments but should hold {}",
+                self.records, records
+            ))),
+            Line::End { .. } => {
+                for line in self.lines.by_ref() {
+                    self.line += 1;
+                    if !line?.trim().is_empty() {
+                        return Err(invalid_data(format!(
+                            "line {}: data after end of proofs",
+                            self.line
+                        )));
+                    }
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Iterator for Reader {
+    type Item = io::Result<Item>;
+
+    fn next(&mut self) -> Option<io::Result<Item>> {
+        if self.done {
+            return None;
+        }
+        let item = self.next_item().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TempDir;
+
+    #[test]
+    fn test_roundtrip_and_truncation() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.proof");
+
+        let circuits = BTreeMap::from([("sha256d".to_string(), "test".to_string())]);
+        let mut header = Header::new(circuits, "log".to_string());
+        header.log = "cd".repeat(32);
+        header.records = 4;
+        let entry = Entry {
+            record: 3,
+            column: "a::b".to_string(),
+            hash_mode: "sha256d".to_string(),
+            hash: Some("00ff".to_string()),
+            // "::" inside the proof bytes must survive the round trip
+            proof: vec![0x3a, 0x3a, 0, 255],
+        };
+        let record = RecordCommitment {
+            record: 3,
+            commitment: "ab".repeat(32),
+            hidden: vec!["a::b".to_string()],
+        };
+        let mut writer = ProofWriter::create(path, header.clone()).unwrap();
+        writer.write_record(record.clone()).unwrap();
+        writer.write(entry.clone()).unwrap();
+        writer.finish().unwrap();
+
+        let read = |path: &str| -> io::Result<(Header, Vec<Item>)> {
+            let reader = Reader::open(path)?;
+            let header = reader.header.clone();
+            Ok((header, reader.collect::<io::Result<_>>()?))
+        };
+        assert_eq!(
+            read(path).unwrap(),
+            (header, vec![Item::Record(record), Item::Proof(entry)])
+        );
+
+        // Drop the end marker, a proof, a record, or add data after the end.
+        let data = fs::read_to_string(path).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        for changed in [
+            lines[..3].join("\n"),
+            [lines[0], lines[1], lines[3]].join("\n"),
+            [lines[0], lines[2], lines[3]].join("\n"),
+            data.clone() + "{}\n",
+        ] {
+            fs::write(path, changed).unwrap();
+            assert!(read(path).is_err());
+        }
+
+        fs::write(path, "3::user::\u{1}\u{2}::").unwrap();
+        assert!(read(path).is_err());
+    }
+}
@@ -0,0 +1,560 @@
+//! Reading and writing log files record by record.
+//!
+//! A log is either a JSON document (one object or an array of objects), JSON
+//! Lines (NDJSON), one object per line, or CSV/TSV with a header row. JSON Lines
+//! and CSV are read and written one record at a time, so that logs larger than
+//! memory can be hidden. Blank lines and lines starting with `#` or `//` in
+//! JSON Lines are skipped.
+//!
+//! A CSV row is read as an object from the column names of the header row to
+//! the cells of the row, all of them strings.
+//!
+//! Syslog files and web server access logs are parsed line by line into
+//! records (see `syslog` and `access`). As they cannot be written back in their
+//! own format, the hidden log is written as JSON Lines.
+//!
+//! Logs of cloud and security tools are read as records too: the `Records` of
+//! AWS CloudTrail documents, Kubernetes audit events, Suricata EVE events,
+//! Zeek logs (see `zeek`) and OpenTelemetry OTLP/JSON log records (see
+//! `otlp`). CloudTrail logs are written back in their `Records` wrapper, the
+//! others as JSON Lines. These formats are detected from the content of the
+//! file.
+
+use crate::{access::LogFormat, otlp, syslog, zeek};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone)]
+pub enum Format {
+    Json,
+    Ndjson,
+    /// CSV with the given delimiter (`\t` for TSV).
+    Csv(u8),
+    Syslog,
+    /// Web server access log in the given `log_format`.
+    Access(LogFormat),
+    CloudTrail,
+    K8sAudit,
+    Suricata,
+    Zeek,
+    Otlp,
+}
+
+impl Format {
+    pub const NAMES: &'static str =
+        "\"json\", \"ndjson\", \"csv\", \"tsv\", \"syslog\", \"access\", \"cloudtrail\", \"k8s-audit\", \"suricata\", \"zeek\" or \"otlp\"";
+
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "json" => Some(Format::Json),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "csv" => Some(Format::Csv(b',')),
+            "tsv" => Some(Format::Csv(b'\t')),
+            "syslog" => Some(Format::Syslog),
+            "access" | "combined" => Some(Format::Access(LogFormat::combined())),
+            "cloudtrail" => Some(Format::CloudTrail),
+            "k8s-audit" => Some(Format::K8sAudit),
+            "suricata" | "eve" => Some(Format::Suricata),
+            "zeek" => Some(Format::Zeek),
+            "otlp" => Some(Format::Otlp),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of `path` from its content, or else its extension.
+    pub fn detect(path: &str) -> Format {
+        let by_extension = match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("ndjson" | "jsonl") => Format::Ndjson,
+            Some("csv") => return Format::Csv(b','),
+            Some("tsv") => return Format::Csv(b'\t'),
+            _ => Format::Json,
+        };
+
+        let Ok(file) = fs::File::open(path) else {
+            return by_extension;
+        };
+        let first_line = BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .find(|line| !line.trim().is_empty());
+        let Some(first_line) = first_line else {
+            return by_extension;
+        };
+        if first_line.starts_with("#separator") || first_line.starts_with("#fields") {
+            return Format::Zeek;
+        }
+
+        // A document spread over several lines has to be read as a whole.
+        let first = match serde_json::from_str(&first_line) {
+            Ok(value) => Some(value),
+            Err(_) if first_line.trim_start().starts_with('{') => fs::read_to_string(path)
+                .ok()
+                .and_then(|document| serde_json::from_str(&document).ok()),
+            Err(_) => None,
+        };
+        first
+            .as_ref()
+            .and_then(Format::of_document)
+            .unwrap_or(by_extension)
+    }
+
+    /// Recognizes the format of a log from its first JSON document.
+    fn of_document(document: &Value) -> Option<Format> {
+        let field = |key: &str| document.get(key);
+        if field("Records").is_some_and(Value::is_array) {
+            Some(Format::CloudTrail)
+        } else if field("resourceLogs").is_some_and(Value::is_array) {
+            Some(Format::Otlp)
+        } else if field("kind").and_then(Value::as_str) == Some("Event")
+            && field("apiVersion")
+                .and_then(Value::as_str)
+                .is_some_and(|v| v.starts_with("audit.k8s.io/"))
+        {
+            Some(Format::K8sAudit)
+        } else if field("event_type").is_some() && field("timestamp").is_some() {
+            Some(Format::Suricata)
+        } else {
+            None
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self.output() {
+            Format::Json | Format::CloudTrail => "json",
+            Format::Csv(b'\t') => "tsv",
+            Format::Csv(_) => "csv",
+            _ => "ndjson",
+        }
+    }
+
+    /// The format a hidden log read in this format is written in.
+    pub fn output(&self) -> Format {
+        match self {
+            Format::Json | Format::Csv(_) | Format::CloudTrail => self.clone(),
+            _ => Format::Ndjson,
+        }
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub type Records = Box<dyn Iterator<Item = io::Result<Value>>>;
+
+/// Opens the log at `path` and returns its records in order.
+pub fn read(path: &str, format: &Format) -> io::Result<Records> {
+    match format {
+        Format::Json => {
+            let records = match serde_json::from_str(&fs::read_to_string(path)?)? {
+                Value::Array(array) if array.iter().all(Value::is_object) => array,
+                Value::Array(_) => {
+                    return Err(invalid_data(
+                        "every element of the array must be a JSON object".to_string(),
+                    ))
+                }
+                Value::Object(obj) => vec![Value::Object(obj)],
+                _ => {
+                    return Err(invalid_data(
+                        "expected a JSON object or an array of objects".to_string(),
+                    ))
+                }
+            };
+            Ok(Box::new(records.into_iter().map(Ok)))
+        }
+        Format::Ndjson => LineReader::open(path, parse_ndjson),
+        Format::K8sAudit => LineReader::open(path, |line| {
+            parse_event(line, Format::K8sAudit, "a Kubernetes audit event")
+        }),
+        Format::Suricata => LineReader::open(path, |line| {
+            parse_event(line, Format::Suricata, "a Suricata EVE event")
+        }),
+        Format::Zeek => {
+            let mut reader = zeek::Reader::default();
+            LineReader::open(path, move |line| reader.parse_line(line))
+        }
+        Format::CloudTrail => read_documents(path, cloudtrail_records),
+        Format::Otlp => read_documents(path, otlp::records),
+        Format::Syslog => LineReader::open(path, |line| syslog::parse(line).map(Some)),
+        Format::Access(log_format) => {
+            let log_format = log_format.clone();
+            LineReader::open(path, move |line| log_format.parse(line).map(Some))
+        }
+        Format::Csv(delimiter) => {
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(*delimiter)
+                .from_path(path)?;
+            let headers = reader.headers()?.clone();
+            Ok(Box::new(reader.into_records().map(move |row| {
+                let row = row?;
+                Ok(Value::Object(
+                    headers
+                        .iter()
+                        .zip(row.iter())
+                        .map(|(column, cell)| (column.to_string(), Value::from(cell)))
+                        .collect::<Map<_, _>>(),
+                ))
+            })))
+        }
+    }
+}
+
+fn parse_ndjson(line: &str) -> Result<Option<Value>, String> {
+    let line = line.trim();
+    if line.starts_with('#') || line.starts_with("//") {
+        return Ok(None);
+    }
+    match serde_json::from_str(line).map_err(|e| e.to_string())? {
+        Value::Object(obj) => Ok(Some(Value::Object(obj))),
+        _ => Err("not a JSON object".to_string()),
+    }
+}
+
+/// Parses a line of a log with one JSON event per line, checking that it is an
+/// event of `format`.
+fn parse_event(line: &str, format: Format, name: &str) -> Result<Option<Value>, String> {
+    let Some(record) = parse_ndjson(line)? else {
+        return Ok(None);
+    };
+    match Format::of_document(&record) {
+        Some(f) if std::mem::discriminant(&f) == std::mem::discriminant(&format) => {
+            Ok(Some(record))
+        }
+        _ => Err(format!("not {}", name)),
+    }
+}
+
+fn cloudtrail_records(document: Value) -> Result<Vec<Value>, String> {
+    match document {
+        Value::Object(mut obj) => match obj.remove("Records") {
+            Some(Value::Array(records)) if records.iter().all(Value::is_object) => Ok(records),
+            _ => Err("expected a CloudTrail document with a Records array of objects".to_string()),
+        },
+        _ => Err("expected a CloudTrail document".to_string()),
+    }
+}
+
+/// Reads a log of one or more JSON documents, each holding a batch of records.
+fn read_documents(
+    path: &str,
+    records: fn(Value) -> Result<Vec<Value>, String>,
+) -> io::Result<Records> {
+    let documents = serde_json::Deserializer::from_reader(BufReader::new(fs::File::open(path)?))
+        .into_iter::<Value>();
+    Ok(Box::new(documents.flat_map(move |document| {
+        match document
+            .map_err(io::Error::from)
+            .and_then(|document| records(document).map_err(invalid_data))
+        {
+            Ok(records) => records.into_iter().map(Ok).collect::<Vec<_>>(),
+            Err(e) => vec![Err(e)],
+        }
+    })))
+}
+
+/// Reads a log with one record per line. `parse` turns a line into a record,
+/// or returns `None` for lines without one. Blank lines are always skipped.
+/// Lines that fail to parse are skipped too, and once the whole file is read
+/// they are all reported in one error, one line each.
+struct LineReader<F> {
+    lines: io::Lines<BufReader<fs::File>>,
+    line: usize,
+    parse: F,
+    errors: Vec<String>,
+}
+
+impl<F> LineReader<F>
+where
+    F: FnMut(&str) -> Result<Option<Value>, String> + 'static,
+{
+    fn open(path: &str, parse: F) -> io::Result<Records> {
+        Ok(Box::new(LineReader {
+            lines: BufReader::new(fs::File::open(path)?).lines(),
+            line: 0,
+            parse,
+            errors: Vec::new(),
+        }))
+    }
+}
+
+impl<F> Iterator for LineReader<F>
+where
+    F: FnMut(&str) -> Result<Option<Value>, String>,
+{
+    type Item = io::Result<Value>;
+
+    fn next(&mut self) -> Option<io::Result<Value>> {
+        loop {
+            self.line += 1;
+            let line = match self.lines.next() {
+                Some(Ok(line)) => line,
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.errors.is_empty() => return None,
+                None => return Some(Err(invalid_data(self.errors.split_off(0).join("\n")))),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match (self.parse)(&line) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(e) => self.errors.push(format!("line {}: {}", self.line, e)),
+            }
+        }
+    }
+}
+
+/// Writes a log record by record. JSON and CloudTrail documents are written by `finish`,
+/// JSON Lines and CSV as the records come. The header row of a CSV log holds
+/// the columns of the first record, in the same order.
+pub struct RecordWriter {
+    format: Format,
+    file: Output,
+    pending: Vec<Value>,
+    headers: Option<Vec<String>>,
+}
+
+enum Output {
+    File(BufWriter<fs::File>),
+    Csv(Box<csv::Writer<fs::File>>),
+}
+
+impl RecordWriter {
+    pub fn create(path: &str, format: &Format) -> io::Result<RecordWriter> {
+        let format = format.output();
+        let file = match format {
+            Format::Csv(delimiter) => Output::Csv(Box::new(
+                csv::WriterBuilder::new()
+                    .delimiter(delimiter)
+                    .from_path(path)?,
+            )),
+            _ => Output::File(BufWriter::new(fs::File::create(path)?)),
+        };
+        Ok(RecordWriter {
+            format,
+            file,
+            pending: Vec::new(),
+            headers: None,
+        })
+    }
+
+    pub fn write(&mut self, record: Value) -> io::Result<()> {
+        match &mut self.file {
+            Output::File(_) if matches!(self.format, Format::Json | Format::CloudTrail) => {
+                self.pending.push(record)
+            }
+            Output::File(file) => {
+                serde_json::to_writer(&mut *file, &record)?;
+                file.write_all(b"\n")?;
+            }
+            Output::Csv(writer) => {
+                let Value::Object(map) = record else {
+                    return Err(invalid_data("a CSV row must be an object".to_string()));
+                };
+                let headers = match &self.headers {
+                    Some(headers) => headers,
+                    None => {
+                        writer.write_record(map.keys())?;
+                        self.headers.insert(map.keys().cloned().collect())
+                    }
+                };
+                let row = headers.iter().map(|column| match map.get(column) {
+                    Some(Value::String(cell)) => cell.clone(),
+                    Some(Value::Null) | None => String::new(),
+                    Some(value) => value.to_string(),
+                });
+                writer.write_record(row)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match &mut self.file {
+            Output::File(file) => {
+                match self.format {
+                    // A single record is written as an object, not as an array.
+                    Format::Json if self.pending.len() == 1 => {
+                        serde_json::to_writer_pretty(&mut *file, &self.pending[0])?
+                    }
+                    Format::Json => serde_json::to_writer_pretty(&mut *file, &self.pending)?,
+                    Format::CloudTrail => {
+                        let document = serde_json::json!({ "Records": self.pending });
+                        serde_json::to_writer_pretty(&mut *file, &document)?
+                    }
+                    _ => (),
+                }
+                file.flush()
+            }
+            Output::Csv(writer) => writer.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TempDir;
+    use serde_json::json;
+
+    #[test]
+    fn test_ndjson() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.ndjson");
+        assert!(matches!(Format::detect(path), Format::Ndjson));
+
+        fs::write(
+            path,
+            "# exported by vector\n{\"a\": 1}\n\n  // comment\n{\"a\": 2, \"b\": [3]}\n",
+        )
+        .unwrap();
+        let records: Vec<Value> = read(path, &Format::Ndjson)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records, vec![json!({"a": 1}), json!({"a": 2, "b": [3]})]);
+
+        let mut writer = RecordWriter::create(path, &Format::Ndjson).unwrap();
+        for record in records.iter() {
+            writer.write(record.clone()).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "{\"a\":1}\n{\"a\":2,\"b\":[3]}\n"
+        );
+
+        // Every line that fails to parse is reported once the file is read.
+        fs::write(path, "{\"a\": 1}\n[1]\n{\"a\": 3}\n{\n").unwrap();
+        let mut records = read(path, &Format::Ndjson).unwrap();
+        assert_eq!(records.next().unwrap().unwrap(), json!({"a": 1}));
+        assert_eq!(records.next().unwrap().unwrap(), json!({"a": 3}));
+        let e = records.next().unwrap().unwrap_err().to_string();
+        let lines: Vec<&str> = e.lines().collect();
+        assert_eq!(lines[0], "line 2: not a JSON object");
+        assert!(lines[1].starts_with("line 4: "), "{}", e);
+        assert_eq!(lines.len(), 2);
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_access_errors() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.log");
+        let line =
+            r#"192.0.2.1 - - [10/Oct/2000:13:55:36 -0700] "GET / HTTP/1.0" 200 2326 "-" "curl""#;
+        fs::write(path, format!("{}\nnot a request\n{}\n- - -\n", line, line)).unwrap();
+
+        // Both bad lines are reported, after the good ones are read.
+        let (records, errors): (Vec<_>, Vec<_>) =
+            read(path, &Format::Access(LogFormat::combined()))
+                .unwrap()
+                .partition(Result::is_ok);
+        assert_eq!(records.len(), 2);
+        assert_eq!(errors.len(), 1);
+        let e = errors.into_iter().next().unwrap().unwrap_err().to_string();
+        let lines: Vec<&str> = e.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("line 2: "), "{}", e);
+        assert!(lines[1].starts_with("line 4: "), "{}", e);
+    }
+
+    #[test]
+    fn test_csv() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.csv");
+        assert!(matches!(Format::detect(path), Format::Csv(b',')));
+
+        let data = "time;user;message\n\
+                    t1;alice;\"hello; world\"\n\
+                    t2;bob;\"two\nlines with \"\"quotes\"\"\"\n";
+        fs::write(path, data).unwrap();
+        let records: Vec<Value> = read(path, &Format::Csv(b';'))
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                json!({"time": "t1", "user": "alice", "message": "hello; world"}),
+                json!({"time": "t2", "user": "bob", "message": "two\nlines with \"quotes\""}),
+            ]
+        );
+        let keys: Vec<&String> = records[0].as_object().unwrap().keys().collect();
+        assert_eq!(keys, vec!["time", "user", "message"]);
+
+        let mut writer = RecordWriter::create(path, &Format::Csv(b';')).unwrap();
+        for record in records.iter() {
+            writer.write(record.clone()).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), data);
+
+        fs::write(path, "a,b\n1,2,3\n").unwrap();
+        assert!(read(path, &Format::Csv(b','))
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.json");
+
+        let cloudtrail = json!({"Records": [
+            {"eventName": "ConsoleLogin", "sourceIPAddress": "192.0.2.1"},
+            {"eventName": "GetObject", "sourceIPAddress": "192.0.2.2"},
+        ]});
+        fs::write(path, serde_json::to_string_pretty(&cloudtrail).unwrap()).unwrap();
+        assert!(matches!(Format::detect(path), Format::CloudTrail));
+        let records: Vec<Value> = read(path, &Format::CloudTrail)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1]["eventName"], json!("GetObject"));
+
+        let mut writer = RecordWriter::create(path, &Format::CloudTrail).unwrap();
+        for record in records {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+        let written: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(written, cloudtrail);
+
+        fs::write(
+            path,
+            "{\"timestamp\": \"2023-10-11T22:14:15.003+0900\", \"event_type\": \"alert\", \"src_ip\": \"192.0.2.1\"}\n\
+             {\"timestamp\": \"2023-10-11T22:14:16.003+0900\", \"event_type\": \"dns\"}\n",
+        )
+        .unwrap();
+        assert!(matches!(Format::detect(path), Format::Suricata));
+        assert_eq!(read(path, &Format::Suricata).unwrap().count(), 2);
+
+        fs::write(
+            path,
+            "{\"kind\": \"Event\", \"apiVersion\": \"audit.k8s.io/v1\", \"user\": {\"username\": \"admin\"}}\n\
+             {\"kind\": \"Pod\"}\n",
+        )
+        .unwrap();
+        assert!(matches!(Format::detect(path), Format::K8sAudit));
+        let mut records = read(path, &Format::K8sAudit).unwrap();
+        assert!(records.next().unwrap().is_ok());
+        let e = records.next().unwrap().unwrap_err();
+        assert_eq!(e.to_string(), "line 2: not a Kubernetes audit event");
+
+        fs::write(path, "#separator \\x09\n#fields\tts\n1.5\n").unwrap();
+        assert!(matches!(Format::detect(path), Format::Zeek));
+        fs::write(path, "{\"resourceLogs\": []}").unwrap();
+        assert!(matches!(Format::detect(path), Format::Otlp));
+        fs::write(path, "[{\"a\": 1}]").unwrap();
+        assert!(matches!(Format::detect(path), Format::Json));
+    }
+}
@@ -0,0 +1,189 @@
+//! Ed25519 signatures over bundles.
+//!
+//! A Groth16 proof only shows that whoever made it knew the hidden values. To
+//! show which log collector hid a log, `hide --sign-key` signs the manifest of
+//! the bundle, which in turn holds the SHA-256 of the hidden log, the proof
+//! file and the verifying key (see `bundle`).
+//!
+//! Keys are stored as one line of text, so that a directory of trusted signers
+//! can be managed by hand:
+//!
+//! ```text
+//! zk4log-ed25519-secret <base64 secret key>
+//! zk4log-ed25519 <base64 public key>
+//! ```
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+const SECRET_TAG: &str = "zk4log-ed25519-secret";
+const PUBLIC_TAG: &str = "zk4log-ed25519";
+/// Value of the `algorithm` field of a signature.
+pub const ALGORITHM: &str = "ed25519";
+
+/// Signature over the manifest of a bundle, stored next to it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Signature {
+    pub algorithm: String,
+    /// Base64 encoded public key of the signer.
+    pub public_key: String,
+    /// Base64 encoded Ed25519 signature.
+    pub signature: String,
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn generate() -> SigningKey {
+    SigningKey::from_bytes(&rand::thread_rng().gen())
+}
+
+/// Short name of a public key for messages, the head of its SHA-256.
+pub fn fingerprint(key: &VerifyingKey) -> String {
+    hex::encode(&Sha256::digest(key.as_bytes())[..8])
+}
+
+pub fn sign(key: &SigningKey, message: &[u8]) -> Signature {
+    Signature {
+        algorithm: ALGORITHM.to_string(),
+        public_key: STANDARD.encode(key.verifying_key().as_bytes()),
+        signature: STANDARD.encode(key.sign(message).to_bytes()),
+    }
+}
+
+impl Signature {
+    /// Checks the signature over `message` and returns the key that made it.
+    pub fn verify(&self, message: &[u8]) -> Result<VerifyingKey, String> {
+        if self.algorithm != ALGORITHM {
+            return Err(format!("unsupported algorithm {}", self.algorithm));
+        }
+        let key = decode(&self.public_key)
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).map_err(|e| e.to_string()))?;
+        let signature =
+            decode(&self.signature).map(|bytes| ed25519_dalek::Signature::from_bytes(&bytes))?;
+        key.verify_strict(message, &signature)
+            .map_err(|_| "the signature does not match".to_string())?;
+        Ok(key)
+    }
+}
+
+fn decode<const N: usize>(data: &str) -> Result<[u8; N], String> {
+    STANDARD
+        .decode(data.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("expected {} base64 encoded bytes", N))
+}
+
+fn read_tagged<const N: usize>(path: &Path, tag: &str) -> io::Result<[u8; N]> {
+    let data = fs::read_to_string(path)?;
+    let key = data
+        .trim()
+        .strip_prefix(tag)
+        .filter(|rest| rest.starts_with(' '))
+        .ok_or_else(|| invalid_data(format!("not a {} key", tag)))?;
+    decode(key).map_err(invalid_data)
+}
+
+/// Writes the secret key readable by its owner only. An existing file is
+/// never overwritten, so that a key in use is not lost by accident.
+pub fn write_signing_key(path: &str, key: &SigningKey) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let data = STANDARD.encode(key.to_bytes());
+    options
+        .open(path)?
+        .write_all(format!("{} {}\n", SECRET_TAG, data).as_bytes())
+}
+
+pub fn read_signing_key(path: &str) -> io::Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&read_tagged(
+        Path::new(path),
+        SECRET_TAG,
+    )?))
+}
+
+pub fn write_public_key(path: &str, key: &VerifyingKey) -> io::Result<()> {
+    let data = STANDARD.encode(key.as_bytes());
+    fs::write(path, format!("{} {}\n", PUBLIC_TAG, data))
+}
+
+/// Reads the public keys of the trusted signers, the `*.pub` files in `dir`,
+/// and returns them with their names, the file names without `.pub`.
+pub fn trusted_signers(dir: &str) -> io::Result<Vec<(String, VerifyingKey)>> {
+    let mut signers = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".pub"))
+        else {
+            continue;
+        };
+        let key = read_tagged(&path, PUBLIC_TAG)
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).map_err(io::Error::other))
+            .map_err(|e| invalid_data(format!("{}: {}", path.display(), e)))?;
+        signers.push((name.to_string(), key));
+    }
+    signers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(signers)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TempDir;
+
+    #[test]
+    fn test_sign_and_trust() {
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path(name);
+
+        let key = generate();
+        write_signing_key(&path("collector.key"), &key).unwrap();
+        write_public_key(&path("collector.pub"), &key.verifying_key()).unwrap();
+        let key = read_signing_key(&path("collector.key")).unwrap();
+        // The secret key is private and never overwritten.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path("collector.key"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let error = write_signing_key(&path("collector.key"), &generate()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(read_signing_key(&path("collector.key")).unwrap(), key);
+        // The secret key is not a public key.
+        assert!(read_tagged::<32>(Path::new(&path("collector.key")), PUBLIC_TAG).is_err());
+
+        let signers = trusted_signers(&dir.path("")).unwrap();
+        assert_eq!(
+            signers,
+            vec![("collector".to_string(), key.verifying_key())]
+        );
+
+        let signature = sign(&key, b"manifest");
+        assert_eq!(signature.verify(b"manifest"), Ok(key.verifying_key()));
+        assert!(signature.verify(b"manifest!").is_err());
+
+        // A signature by another key with the public key swapped in.
+        let mut forged = sign(&generate(), b"manifest");
+        forged.public_key = signature.public_key.clone();
+        assert!(forged.verify(b"manifest").is_err());
+    }
+}
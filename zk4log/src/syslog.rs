@@ -0,0 +1,261 @@
+//! Parser for syslog messages (RFC 5424 and RFC 3164).
+//!
+//! Each message becomes a record with the columns `facility`, `severity`,
+//! `version`, `timestamp`, `host`, `app_name`, `procid`, `msgid`,
+//! `structured_data` and `msg`. Fields that a message does not have, or that
+//! hold the RFC 5424 nil value `-`, are left out. Structured data is kept as an
+//! object of SD-IDs to their parameters, so that single parameters can be
+//! hidden by path (e.g. `structured_data.origin.ip`).
+
+use serde_json::{Map, Value};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Parses one syslog line.
+pub fn parse(line: &str) -> Result<Value, String> {
+    let mut record = Map::new();
+    let mut rest = line;
+
+    if let Some(after) = line.strip_prefix('<') {
+        let end = after.find('>').ok_or("unterminated PRI")?;
+        let pri: u8 = after[..end]
+            .parse()
+            .ok()
+            .filter(|pri| *pri < 192)
+            .ok_or("invalid PRI")?;
+        record.insert("facility".to_string(), Value::from(pri / 8));
+        record.insert("severity".to_string(), Value::from(pri % 8));
+        rest = &after[end + 1..];
+
+        if let Some((version, after)) = rest.split_once(' ') {
+            if !version.is_empty()
+                && version.len() <= 2
+                && version.bytes().all(|b| b.is_ascii_digit())
+            {
+                record.insert("version".to_string(), Value::from(version));
+                parse_5424(after, &mut record)?;
+                return Ok(Value::Object(record));
+            }
+        }
+    }
+
+    parse_3164(rest, &mut record)?;
+    Ok(Value::Object(record))
+}
+
+/// Splits off the next space separated field.
+fn field(s: &str) -> Result<(&str, &str), String> {
+    match s.split_once(' ') {
+        Some((field, rest)) => Ok((field, rest)),
+        None if !s.is_empty() => Ok((s, "")),
+        None => Err("missing header field".to_string()),
+    }
+}
+
+fn insert(record: &mut Map<String, Value>, column: &str, value: &str) {
+    if value != "-" && !value.is_empty() {
+        record.insert(column.to_string(), Value::from(value));
+    }
+}
+
+fn parse_5424(s: &str, record: &mut Map<String, Value>) -> Result<(), String> {
+    let mut rest = s;
+    for column in ["timestamp", "host", "app_name", "procid", "msgid"] {
+        let (value, after) = field(rest)?;
+        insert(record, column, value);
+        rest = after;
+    }
+
+    let rest = if let Some(after) = rest.strip_prefix('-') {
+        after
+    } else {
+        let (sd, after) = parse_structured_data(rest)?;
+        record.insert("structured_data".to_string(), Value::Object(sd));
+        after
+    };
+
+    if let Some(msg) = rest.strip_prefix(' ') {
+        insert(record, "msg", msg.strip_prefix('\u{feff}').unwrap_or(msg));
+    } else if !rest.is_empty() {
+        return Err("invalid structured data".to_string());
+    }
+    Ok(())
+}
+
+/// Parses `[id name="value" ...]...` and returns it with the rest of the line.
+fn parse_structured_data(s: &str) -> Result<(Map<String, Value>, &str), String> {
+    let mut sd = Map::new();
+    let mut rest = s;
+
+    while let Some(after) = rest.strip_prefix('[') {
+        let end = after
+            .find([' ', ']'])
+            .ok_or("unterminated structured data")?;
+        let id = &after[..end];
+        let mut params = Map::new();
+        rest = &after[end..];
+
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let after = rest.strip_prefix(' ').ok_or("invalid structured data")?;
+            let (name, after) = after.split_once("=\"").ok_or("invalid SD-PARAM")?;
+
+            // PARAM-VALUE escapes '"', '\' and ']' with a backslash.
+            let mut value = String::new();
+            let mut chars = after.char_indices();
+            let end = loop {
+                match chars.next().ok_or("unterminated PARAM-VALUE")? {
+                    (_, '\\') => match chars.next() {
+                        Some((_, c @ ('"' | '\\' | ']'))) => value.push(c),
+                        Some((_, c)) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                        None => return Err("unterminated PARAM-VALUE".to_string()),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            params.insert(name.to_string(), Value::from(value));
+            rest = &after[end + 1..];
+        }
+
+        sd.insert(id.to_string(), Value::Object(params));
+    }
+
+    if sd.is_empty() {
+        return Err("invalid structured data".to_string());
+    }
+    Ok((sd, rest))
+}
+
+fn parse_3164(s: &str, record: &mut Map<String, Value>) -> Result<(), String> {
+    // "Oct 11 22:14:15", or an RFC 3339 timestamp as written by rsyslog
+    let classic = MONTHS.iter().any(|m| s.starts_with(m)) && s.len() > 16;
+    let (timestamp, rest) = if classic && s.is_char_boundary(15) {
+        (&s[..15], &s[15..])
+    } else {
+        let (timestamp, rest) = field(s)?;
+        if !timestamp.contains('T') || !timestamp.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err("not a syslog message".to_string());
+        }
+        (timestamp, rest)
+    };
+    let rest = rest.trim_start_matches(' ');
+    insert(record, "timestamp", timestamp);
+
+    let (host, rest) = field(rest)?;
+    insert(record, "host", host);
+
+    // "app[123]: message" or "app: message"
+    let tag_end = rest
+        .find(": ")
+        .filter(|&end| !rest[..end].contains(' '))
+        .or_else(|| {
+            rest.strip_suffix(':')
+                .map(str::len)
+                .filter(|&end| !rest[..end].contains(' '))
+        });
+    let msg = match tag_end {
+        Some(end) => {
+            let tag = &rest[..end];
+            match tag.strip_suffix(']').and_then(|t| t.split_once('[')) {
+                Some((app, procid)) => {
+                    insert(record, "app_name", app);
+                    insert(record, "procid", procid);
+                }
+                None => insert(record, "app_name", tag),
+            }
+            rest[end + 1..]
+                .strip_prefix(' ')
+                .unwrap_or(&rest[end + 1..])
+        }
+        None => rest,
+    };
+    insert(record, "msg", msg);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_rfc5424() {
+        let line = r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"][origin ip="192.0.2.1" note="a \"quoted\] value"] An application event"#;
+        assert_eq!(
+            parse(line).unwrap(),
+            json!({
+                "facility": 20,
+                "severity": 5,
+                "version": "1",
+                "timestamp": "2003-10-11T22:14:15.003Z",
+                "host": "mymachine.example.com",
+                "app_name": "evntslog",
+                "msgid": "ID47",
+                "structured_data": {
+                    "exampleSDID@32473": {"iut": "3", "eventSource": "Application", "eventID": "1011"},
+                    "origin": {"ip": "192.0.2.1", "note": "a \"quoted] value"},
+                },
+                "msg": "An application event",
+            })
+        );
+
+        assert_eq!(
+            parse("<34>1 2003-10-11T22:14:15.003Z host su - - - \u{feff}'su root' failed").unwrap(),
+            json!({
+                "facility": 4,
+                "severity": 2,
+                "version": "1",
+                "timestamp": "2003-10-11T22:14:15.003Z",
+                "host": "host",
+                "app_name": "su",
+                "msg": "'su root' failed",
+            })
+        );
+        assert!(parse("<34>1 2003-10-11T22:14:15.003Z host su - - [broken").is_err());
+        assert!(parse("<999>1 - - - - - -").is_err());
+    }
+
+    #[test]
+    fn test_rfc3164() {
+        assert_eq!(
+            parse("<34>Oct 11 22:14:15 mymachine su[230]: 'su root' failed for lonvick").unwrap(),
+            json!({
+                "facility": 4,
+                "severity": 2,
+                "timestamp": "Oct 11 22:14:15",
+                "host": "mymachine",
+                "app_name": "su",
+                "procid": "230",
+                "msg": "'su root' failed for lonvick",
+            })
+        );
+        assert_eq!(
+            parse("Oct  1 02:00:01 web1 CRON: session opened for user root").unwrap(),
+            json!({
+                "timestamp": "Oct  1 02:00:01",
+                "host": "web1",
+                "app_name": "CRON",
+                "msg": "session opened for user root",
+            })
+        );
+        assert_eq!(
+            parse("2023-10-11T22:14:15.003+09:00 web1 kernel: eth0 link up").unwrap(),
+            json!({
+                "timestamp": "2023-10-11T22:14:15.003+09:00",
+                "host": "web1",
+                "app_name": "kernel",
+                "msg": "eth0 link up",
+            })
+        );
+        assert!(parse("hello world").is_err());
+    }
+}
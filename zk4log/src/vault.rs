@@ -0,0 +1,219 @@
+//! The salt vault written next to the hidden log.
+//!
+//! It holds the salt of every hidden cell, so that whoever can decrypt it can
+//! later open a cell by recomputing its hash from the original value. The vault
+//! is a JSON document encrypted with [age](https://age-encryption.org), either
+//! to a passphrase or to an X25519 recipient key.
+
+use age::secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, Read, Write},
+    iter,
+};
+
+/// Value of the `format` field, identifying zk4log salt vaults.
+pub const FORMAT: &str = "zk4log-vault";
+/// Current version of the salt vault format.
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vault {
+    pub format: String,
+    pub version: u32,
+    /// ID of the hidden log the salts belong to.
+    pub log_id: String,
+    /// Hash the cells were hidden with, unless their salt entry names another.
+    /// Vaults written before hash modes could be chosen have none and were
+    /// made with `sha256d`.
+    #[serde(default = "default_hash")]
+    pub hash: String,
+    pub salts: Vec<SaltEntry>,
+}
+
+fn default_hash() -> String {
+    "sha256d".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaltEntry {
+    pub record: usize,
+    pub column: String,
+    pub salt: String,
+    /// Hash of the cell, if a policy rule hid it with another hash than the
+    /// vault's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// What the vault is encrypted to. A vault can be written with any of them,
+/// but only read with a passphrase or an identity (secret key).
+pub enum VaultKey {
+    Passphrase(String),
+    Recipient(age::x25519::Recipient),
+    Identity(age::x25519::Identity),
+}
+
+impl VaultKey {
+    /// Reads an age identity file (as made by `age-keygen`).
+    pub fn read_identity(path: &str) -> io::Result<VaultKey> {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+            .ok_or_else(|| invalid_data("no AGE-SECRET-KEY found".to_string()))?
+            .parse()
+            .map(VaultKey::Identity)
+            .map_err(|e: &str| invalid_data(e.to_string()))
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Writes an encrypted vault salt by salt, so that the salts of a large log
+/// need not be held in memory. `finish` must be called once all salts are
+/// written.
+pub struct VaultWriter {
+    writer: age::stream::StreamWriter<io::BufWriter<fs::File>>,
+    hash: String,
+    count: usize,
+}
+
+impl VaultWriter {
+    /// Creates the vault for the log `log_id` hidden with `hash` at `path`,
+    /// encrypted to `key`.
+    pub fn create(path: &str, key: &VaultKey, log_id: &str, hash: &str) -> io::Result<VaultWriter> {
+        let encryptor = match key {
+            VaultKey::Passphrase(passphrase) => {
+                age::Encryptor::with_user_passphrase(Secret::new(passphrase.clone()))
+            }
+            VaultKey::Recipient(recipient) => {
+                age::Encryptor::with_recipients(vec![Box::new(recipient.clone())])
+                    .expect("one recipient is given")
+            }
+            VaultKey::Identity(identity) => {
+                age::Encryptor::with_recipients(vec![Box::new(identity.to_public())])
+                    .expect("one recipient is given")
+            }
+        };
+
+        let mut writer = encryptor
+            .wrap_output(io::BufWriter::new(fs::File::create(path)?))
+            .map_err(io::Error::other)?;
+        // The fields of `Vault`, with the salts written one by one.
+        write!(
+            writer,
+            "{{\"format\":{},\"version\":{},\"log_id\":{},\"hash\":{},\"salts\":[",
+            serde_json::to_string(FORMAT)?,
+            VERSION,
+            serde_json::to_string(log_id)?,
+            serde_json::to_string(hash)?
+        )?;
+        Ok(VaultWriter {
+            writer,
+            hash: hash.to_string(),
+            count: 0,
+        })
+    }
+
+    /// Adds the salt of a cell hidden with `hash`.
+    pub fn push(&mut self, record: usize, column: &str, salt: &str, hash: &str) -> io::Result<()> {
+        if self.count > 0 {
+            self.writer.write_all(b",")?;
+        }
+        self.count += 1;
+        serde_json::to_writer(
+            &mut self.writer,
+            &SaltEntry {
+                record,
+                column: column.to_string(),
+                salt: salt.to_string(),
+                hash: (hash != self.hash).then(|| hash.to_string()),
+            },
+        )?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.write_all(b"]}")?;
+        self.writer.finish()?.flush()
+    }
+}
+
+impl Vault {
+    /// Reads and decrypts the vault at `path` with `key`.
+    pub fn read(path: &str, key: &VaultKey) -> io::Result<Vault> {
+        let encrypted = fs::read(path)?;
+        let decryptor = age::Decryptor::new(&encrypted[..]).map_err(io::Error::other)?;
+        let mut reader = match (decryptor, key) {
+            (age::Decryptor::Passphrase(d), VaultKey::Passphrase(passphrase)) => d
+                .decrypt(&Secret::new(passphrase.clone()), None)
+                .map_err(io::Error::other)?,
+            (age::Decryptor::Recipients(d), VaultKey::Identity(identity)) => d
+                .decrypt(iter::once(identity as &dyn age::Identity))
+                .map_err(io::Error::other)?,
+            (age::Decryptor::Passphrase(_), _) => {
+                return Err(invalid_data(
+                    "the vault is encrypted with a passphrase".to_string(),
+                ))
+            }
+            (age::Decryptor::Recipients(_), _) => {
+                return Err(invalid_data(
+                    "the vault is encrypted to a recipient, an identity is needed".to_string(),
+                ))
+            }
+        };
+
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext)?;
+        let vault: Vault = serde_json::from_slice(&plaintext)?;
+        if vault.format != FORMAT || vault.version != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported vault format {} version {}",
+                vault.format, vault.version
+            )));
+        }
+        Ok(vault)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TempDir;
+
+    #[test]
+    fn test_vault_roundtrip() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("test.vault");
+
+        let identity = age::x25519::Identity::generate();
+        let recipient = VaultKey::Recipient(identity.to_public());
+        let mut writer = VaultWriter::create(path, &recipient, "log", "blake2s").unwrap();
+        writer
+            .push(0, "user", "2rL9AkP0zS6E8yYX", "blake2s")
+            .unwrap();
+        writer
+            .push(3, "user.ip", "Zp8dOa1bQy7mNc2x", "mimc")
+            .unwrap();
+        writer.finish().unwrap();
+
+        let encrypted = fs::read(path).unwrap();
+        assert!(!String::from_utf8_lossy(&encrypted).contains("2rL9AkP0zS6E8yYX"));
+
+        let read = Vault::read(path, &VaultKey::Identity(identity)).unwrap();
+        assert_eq!(read.log_id, "log");
+        assert_eq!(read.hash, "blake2s");
+        assert_eq!(read.salts.len(), 2);
+        assert_eq!(read.salts[0].hash, None);
+        assert_eq!(read.salts[1].hash.as_deref(), Some("mimc"));
+        assert_eq!(read.salts[1].column, "user.ip");
+        assert_eq!(read.salts[1].salt, "Zp8dOa1bQy7mNc2x");
+
+        let wrong = VaultKey::Identity(age::x25519::Identity::generate());
+        assert!(Vault::read(path, &wrong).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Parser for Zeek logs in their default tab separated format.
+//!
+//! A Zeek log starts with `#` header lines naming the separators and, in
+//! `#fields`, the columns of the log. Every data line becomes a record from
+//! those column names (`ts`, `uid`, `id.orig_h`, ...) to its values, typed by
+//! the `#types` header: counts and ports are numbers, sets and vectors are
+//! arrays. Unset fields (`-`) are left out, as in Zeek's own JSON logs.
+
+use serde_json::{Map, Number, Value};
+
+pub struct Reader {
+    separator: String,
+    set_separator: String,
+    empty_field: String,
+    unset_field: String,
+    fields: Vec<String>,
+    types: Vec<String>,
+}
+
+impl Default for Reader {
+    fn default() -> Reader {
+        Reader {
+            separator: "\t".to_string(),
+            set_separator: ",".to_string(),
+            empty_field: "(empty)".to_string(),
+            unset_field: "-".to_string(),
+            fields: Vec::new(),
+            types: Vec::new(),
+        }
+    }
+}
+
+impl Reader {
+    /// Parses one line of the log. Header lines update the reader and return
+    /// `None`.
+    pub fn parse_line(&mut self, line: &str) -> Result<Option<Value>, String> {
+        if let Some(separator) = line.strip_prefix("#separator ") {
+            self.separator = unescape(separator)?;
+            return Ok(None);
+        }
+        if let Some(header) = line.strip_prefix('#') {
+            let (key, value) = header.split_once(&self.separator).unwrap_or((header, ""));
+            let list = || value.split(&self.separator).map(str::to_string).collect();
+            match key {
+                "set_separator" => self.set_separator = value.to_string(),
+                "empty_field" => self.empty_field = value.to_string(),
+                "unset_field" => self.unset_field = value.to_string(),
+                "fields" => self.fields = list(),
+                "types" => self.types = list(),
+                // #path, #open, #close
+                _ => (),
+            }
+            return Ok(None);
+        }
+
+        if self.fields.is_empty() {
+            return Err("data before the #fields header".to_string());
+        }
+        let values: Vec<&str> = line.split(&self.separator).collect();
+        if values.len() != self.fields.len() {
+            return Err(format!(
+                "{} values for {} fields",
+                values.len(),
+                self.fields.len()
+            ));
+        }
+
+        let mut record = Map::new();
+        for (i, (field, value)) in self.fields.iter().zip(values).enumerate() {
+            if value == self.unset_field {
+                continue;
+            }
+            let ty = self.types.get(i).map_or("string", String::as_str);
+            record.insert(field.clone(), self.value(ty, value));
+        }
+        Ok(Some(Value::Object(record)))
+    }
+
+    fn value(&self, ty: &str, value: &str) -> Value {
+        let element = ty
+            .strip_prefix("set[")
+            .or_else(|| ty.strip_prefix("vector["))
+            .and_then(|t| t.strip_suffix(']'));
+        if let Some(element) = element {
+            if value == self.empty_field {
+                return Value::Array(Vec::new());
+            }
+            return value
+                .split(&self.set_separator)
+                .map(|v| self.value(element, v))
+                .collect();
+        }
+        if value == self.empty_field {
+            return Value::from("");
+        }
+
+        let typed = match ty {
+            "count" | "int" | "port" => value.parse::<i64>().ok().map(Value::from),
+            "double" | "time" | "interval" => value
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .map(Value::Number),
+            "bool" => match value {
+                "T" => Some(Value::Bool(true)),
+                "F" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            _ => None,
+        };
+        typed.unwrap_or_else(|| Value::from(value))
+    }
+}
+
+/// Decodes the `\xHH` escapes of the `#separator` header.
+fn unescape(s: &str) -> Result<String, String> {
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(i) = rest.find("\\x") {
+        out.push_str(&rest[..i]);
+        let byte = rest
+            .get(i + 2..i + 4)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or("invalid #separator")?;
+        out.push(byte as char);
+        rest = &rest[i + 4..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_zeek() {
+        let log = "#separator \\x09\n\
+                   #set_separator\t,\n\
+                   #empty_field\t(empty)\n\
+                   #unset_field\t-\n\
+                   #path\tconn\n\
+                   #fields\tts\tuid\tid.orig_h\tid.orig_p\tservice\tduration\tlocal_orig\ttunnel_parents\n\
+                   #types\ttime\tstring\taddr\tport\tstring\tinterval\tbool\tset[string]\n\
+                   1300475167.096535\tCRCC5OdZPbp5jAfcc\t141.142.220.202\t5353\tdns\t-\tF\t(empty)\n\
+                   1300475168.853899\tC5bLoe2Mvxqhawzqqd\t141.142.220.118\t43927\t-\t0.000435\tT\ta,b\n\
+                   #close\t2011-03-18-19-06-13\n";
+        let mut reader = Reader::default();
+        let records: Vec<Value> = log
+            .lines()
+            .filter_map(|line| reader.parse_line(line).unwrap())
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                json!({
+                    "ts": 1300475167.096535,
+                    "uid": "CRCC5OdZPbp5jAfcc",
+                    "id.orig_h": "141.142.220.202",
+                    "id.orig_p": 5353,
+                    "service": "dns",
+                    "local_orig": false,
+                    "tunnel_parents": [],
+                }),
+                json!({
+                    "ts": 1300475168.853899,
+                    "uid": "C5bLoe2Mvxqhawzqqd",
+                    "id.orig_h": "141.142.220.118",
+                    "id.orig_p": 43927,
+                    "duration": 0.000435,
+                    "local_orig": true,
+                    "tunnel_parents": ["a", "b"],
+                }),
+            ]
+        );
+
+        assert!(reader.parse_line("1\t2").is_err());
+        assert!(Reader::default().parse_line("1\t2").is_err());
+    }
+}
@@ -1,66 +1,539 @@
+use crate::{commitment, mimc};
 use bellman::groth16::{Parameters, PreparedVerifyingKey, Proof};
 use bellman::{
     gadgets::{
         boolean::{AllocatedBit, Boolean},
+        multieq::MultiEq,
         multipack,
+        num::AllocatedNum,
         sha256::sha256,
+        uint32::UInt32,
     },
-    groth16, Circuit, ConstraintSystem, SynthesisError,
+    groth16, Circuit, ConstraintSystem, LinearCombination, SynthesisError,
 };
 use bls12_381::Bls12;
-use ff::PrimeField;
+use ff::{PrimeField, PrimeFieldBits};
 use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
 
-/// Our own SHA-256d gadget. Input and output are in little-endian bit order.
-fn sha256d<Scalar: PrimeField, CS: ConstraintSystem<Scalar>>(
+/// Size of a SHA-256 or BLAKE2s message block in bytes.
+pub const BLOCK_BYTES: usize = 64;
+
+/// Hash the hidden cells are made with. Every hash has its own circuit, so
+/// proofs, parameters and verifying keys are only valid for one hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Hash {
+    /// SHA-256 applied twice.
+    #[default]
+    Sha256d,
+    Sha256,
+    /// BLAKE2s-256 without key or personalization. Its circuit is smaller than
+    /// the SHA-256 ones, so proofs are made faster.
+    Blake2s,
+    /// MiMC over the BLS12-381 scalar field (see `mimc`). Its circuit works on
+    /// field elements instead of bits and is far smaller than the others.
+    Mimc,
+}
+
+impl Hash {
+    pub const NAMES: &'static str = "\"sha256d\", \"sha256\", \"blake2s\" or \"mimc\"";
+
+    pub fn parse(name: &str) -> Option<Hash> {
+        match name {
+            "sha256d" => Some(Hash::Sha256d),
+            "sha256" => Some(Hash::Sha256),
+            "blake2s" => Some(Hash::Blake2s),
+            "mimc" => Some(Hash::Mimc),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Hash::Sha256d => "sha256d",
+            Hash::Sha256 => "sha256",
+            Hash::Blake2s => "blake2s",
+            Hash::Mimc => "mimc",
+        }
+    }
+
+    /// The hash of `preimage`, computed natively. This is the value a proof
+    /// shows knowledge of a preimage for.
+    pub fn digest(self, preimage: &[u8]) -> [u8; 32] {
+        match self {
+            Hash::Sha256d => Sha256::digest(Sha256::digest(preimage)).into(),
+            Hash::Sha256 => Sha256::digest(preimage).into(),
+            Hash::Blake2s => *blake2s_simd::blake2s(preimage).as_array(),
+            Hash::Mimc => mimc::hash(preimage),
+        }
+    }
+
+    /// Number of blocks a preimage of `len` bytes occupies once padded. The
+    /// blocks of MiMC are the chunks of `mimc::CHUNK_BYTES` bytes it absorbs.
+    pub fn blocks_for(self, len: usize) -> usize {
+        match self {
+            Hash::Sha256d | Hash::Sha256 => (len + 9).div_ceil(BLOCK_BYTES),
+            // An empty preimage still takes one (zero) block.
+            Hash::Blake2s => len.div_ceil(BLOCK_BYTES).max(1),
+            Hash::Mimc => len.div_ceil(mimc::CHUNK_BYTES).max(1),
+        }
+    }
+
+    /// Longest preimage (in bytes) a circuit of `blocks` blocks can hash.
+    pub fn capacity(self, blocks: usize) -> usize {
+        match self {
+            Hash::Sha256d | Hash::Sha256 => blocks * BLOCK_BYTES - 9,
+            Hash::Blake2s => blocks * BLOCK_BYTES,
+            Hash::Mimc => blocks * mimc::CHUNK_BYTES,
+        }
+    }
+
+    /// Identifies the circuit (and so the verifying key) a proof was made for.
+    pub fn circuit_id(self, blocks: usize) -> String {
+        format!("zk4log/{}/v2/{}", self.name(), blocks)
+    }
+}
+
+#[allow(clippy::unreadable_literal)]
+const ROUND_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// Initial hash value of SHA-256, which BLAKE2s shares.
+#[allow(clippy::unreadable_literal)]
+const IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Message word permutations of the BLAKE2s rounds.
+const SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// The cell a proof belongs to. It is exposed as public inputs next to the
+/// hash, so a proof only verifies for the log, record and column it was made
+/// for and cannot be moved to another cell holding the same hash. It also
+/// holds the head of the `commitment::Chain` over the whole log, so the proof
+/// fails as well once the commitment of any record, including records without
+/// hidden cells, is changed or the log is truncated or extended.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub log_id: String,
+    pub record: u64,
+    pub column: String,
+    pub commitment: [u8; commitment::BYTES],
+}
+
+impl Binding {
+    /// Length of `to_bytes`.
+    const BYTES: usize = 32 + 32 + 8;
+
+    /// `SHA-256(log_id) || SHA-256(commitment || column) || record (u64 LE)`
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::BYTES);
+        bytes.extend_from_slice(&Sha256::digest(self.log_id.as_bytes()));
+        let mut cell = Sha256::new();
+        cell.update(self.commitment);
+        cell.update(self.column.as_bytes());
+        bytes.extend_from_slice(&cell.finalize());
+        bytes.extend_from_slice(&self.record.to_le_bytes());
+        bytes
+    }
+
+    fn inputs<Scalar: PrimeField>(&self) -> Vec<Scalar> {
+        multipack::compute_multipacking(&multipack::bytes_to_bits_le(&self.to_bytes()))
+    }
+}
+
+/// Applies the SHA-256 message padding to `m` and fills the remaining blocks
+/// up to `blocks` with zeros.
+fn pad(m: &[u8], blocks: usize) -> Vec<u8> {
+    let mut padded = m.to_vec();
+    padded.push(0x80);
+    padded.resize(Hash::Sha256.blocks_for(m.len()) * BLOCK_BYTES - 8, 0);
+    padded.extend_from_slice(&(m.len() as u64 * 8).to_be_bytes());
+    padded.resize(blocks * BLOCK_BYTES, 0);
+    padded
+}
+
+/// The SHA-256 compression function. bellman only exposes it through
+/// `sha256` with a padding fixed at synthesis time, so we need our own copy to
+/// chain a variable number of blocks. Input bits are in big-endian order.
+#[allow(clippy::many_single_char_names)]
+fn sha256_compression<Scalar, CS>(
+    cs: CS,
+    input: &[Boolean],
+    current_hash_value: &[UInt32],
+) -> Result<Vec<UInt32>, SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    assert_eq!(input.len(), 512);
+    assert_eq!(current_hash_value.len(), 8);
+
+    let mut w = input
+        .chunks(32)
+        .map(UInt32::from_bits_be)
+        .collect::<Vec<_>>();
+
+    // Combining the u32 additions of a round saves constraints
+    let mut cs = MultiEq::new(cs);
+
+    for i in 16..64 {
+        let cs = &mut cs.namespace(|| format!("w extension {}", i));
+
+        // s0 := (w[i-15] rightrotate 7) xor (w[i-15] rightrotate 18) xor (w[i-15] rightshift 3)
+        let mut s0 = w[i - 15].rotr(7);
+        s0 = s0.xor(cs.namespace(|| "first xor for s0"), &w[i - 15].rotr(18))?;
+        s0 = s0.xor(cs.namespace(|| "second xor for s0"), &w[i - 15].shr(3))?;
+
+        // s1 := (w[i-2] rightrotate 17) xor (w[i-2] rightrotate 19) xor (w[i-2] rightshift 10)
+        let mut s1 = w[i - 2].rotr(17);
+        s1 = s1.xor(cs.namespace(|| "first xor for s1"), &w[i - 2].rotr(19))?;
+        s1 = s1.xor(cs.namespace(|| "second xor for s1"), &w[i - 2].shr(10))?;
+
+        // w[i] := w[i-16] + s0 + w[i-7] + s1
+        let tmp = UInt32::addmany(
+            cs.namespace(|| "computation of w[i]"),
+            &[w[i - 16].clone(), s0, w[i - 7].clone(), s1],
+        )?;
+        w.push(tmp);
+    }
+
+    let mut a = current_hash_value[0].clone();
+    let mut b = current_hash_value[1].clone();
+    let mut c = current_hash_value[2].clone();
+    let mut d = current_hash_value[3].clone();
+    let mut e = current_hash_value[4].clone();
+    let mut f = current_hash_value[5].clone();
+    let mut g = current_hash_value[6].clone();
+    let mut h = current_hash_value[7].clone();
+
+    for (i, k) in ROUND_CONSTANTS.iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("compression round {}", i));
+
+        // S1 := (e rightrotate 6) xor (e rightrotate 11) xor (e rightrotate 25)
+        let mut s1 = e.rotr(6);
+        s1 = s1.xor(cs.namespace(|| "first xor for s1"), &e.rotr(11))?;
+        s1 = s1.xor(cs.namespace(|| "second xor for s1"), &e.rotr(25))?;
+
+        // ch := (e and f) xor ((not e) and g)
+        let ch = UInt32::sha256_ch(cs.namespace(|| "ch"), &e, &f, &g)?;
+
+        // S0 := (a rightrotate 2) xor (a rightrotate 13) xor (a rightrotate 22)
+        let mut s0 = a.rotr(2);
+        s0 = s0.xor(cs.namespace(|| "first xor for s0"), &a.rotr(13))?;
+        s0 = s0.xor(cs.namespace(|| "second xor for s0"), &a.rotr(22))?;
+
+        // maj := (a and b) xor (a and c) xor (b and c)
+        let maj = UInt32::sha256_maj(cs.namespace(|| "maj"), &a, &b, &c)?;
+
+        // temp1 := h + S1 + ch + k[i] + w[i]
+        let temp1 = [h, s1, ch, UInt32::constant(*k), w[i].clone()];
+
+        h = g;
+        g = f;
+        f = e;
+        // e := d + temp1
+        e = UInt32::addmany(cs.namespace(|| "new e"), &[temp1.as_slice(), &[d]].concat())?;
+        d = c;
+        c = b;
+        b = a;
+        // a := temp1 + S0 + maj
+        a = UInt32::addmany(
+            cs.namespace(|| "new a"),
+            &[temp1.as_slice(), &[s0, maj]].concat(),
+        )?;
+    }
+
+    // Add the compressed chunk to the current hash value
+    [a, b, c, d, e, f, g, h]
+        .into_iter()
+        .zip(current_hash_value)
+        .enumerate()
+        .map(|(i, (x, cur))| {
+            UInt32::addmany(cs.namespace(|| format!("new h{}", i)), &[cur.clone(), x])
+        })
+        .collect()
+}
+
+/// The BLAKE2s mixing function G on the words `a`, `b`, `c` and `d` of `v`.
+#[allow(clippy::too_many_arguments)]
+fn mixing_g<Scalar, CS, M>(
+    mut cs: M,
+    v: &mut [UInt32],
+    (a, b, c, d): (usize, usize, usize, usize),
+    x: &UInt32,
+    y: &UInt32,
+) -> Result<(), SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+    M: ConstraintSystem<Scalar, Root = MultiEq<Scalar, CS>>,
+{
+    v[a] = UInt32::addmany(
+        cs.namespace(|| "mixing step 1"),
+        &[v[a].clone(), v[b].clone(), x.clone()],
+    )?;
+    v[d] = v[d].xor(cs.namespace(|| "mixing step 2"), &v[a])?.rotr(16);
+    v[c] = UInt32::addmany(
+        cs.namespace(|| "mixing step 3"),
+        &[v[c].clone(), v[d].clone()],
+    )?;
+    v[b] = v[b].xor(cs.namespace(|| "mixing step 4"), &v[c])?.rotr(12);
+    v[a] = UInt32::addmany(
+        cs.namespace(|| "mixing step 5"),
+        &[v[a].clone(), v[b].clone(), y.clone()],
+    )?;
+    v[d] = v[d].xor(cs.namespace(|| "mixing step 6"), &v[a])?.rotr(8);
+    v[c] = UInt32::addmany(
+        cs.namespace(|| "mixing step 7"),
+        &[v[c].clone(), v[d].clone()],
+    )?;
+    v[b] = v[b].xor(cs.namespace(|| "mixing step 8"), &v[c])?.rotr(7);
+    Ok(())
+}
+
+/// The BLAKE2s compression function. bellman's `blake2s` gadget fixes the
+/// byte counter and the final block at synthesis time, so like
+/// `sha256_compression` we need our own copy to hash preimages of any length
+/// with the same circuit. `counter` is the number of bytes hashed up to the end
+/// of this block and `last` is set for the final block.
+fn blake2s_compression<Scalar, CS>(
+    cs: CS,
+    h: &[UInt32],
+    m: &[UInt32],
+    counter: &UInt32,
+    last: &Boolean,
+) -> Result<Vec<UInt32>, SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    assert_eq!(h.len(), 8);
+    assert_eq!(m.len(), 16);
+
+    let mut cs = MultiEq::new(cs);
+    let mut v: Vec<UInt32> = h.iter().cloned().chain(IV.map(UInt32::constant)).collect();
+    // Preimages are far shorter than 2^32 bytes, so the high word of the
+    // counter stays zero.
+    v[12] = v[12].xor(cs.namespace(|| "counter"), counter)?;
+    v[14] = v[14].xor(
+        cs.namespace(|| "final block"),
+        &UInt32::from_bits(&vec![last.clone(); 32]),
+    )?;
+
+    const COLUMNS_AND_DIAGONALS: [(usize, usize, usize, usize); 8] = [
+        (0, 4, 8, 12),
+        (1, 5, 9, 13),
+        (2, 6, 10, 14),
+        (3, 7, 11, 15),
+        (0, 5, 10, 15),
+        (1, 6, 11, 12),
+        (2, 7, 8, 13),
+        (3, 4, 9, 14),
+    ];
+    for (i, s) in SIGMA.iter().enumerate() {
+        let cs = &mut cs.namespace(|| format!("round {}", i));
+        for (j, words) in COLUMNS_AND_DIAGONALS.into_iter().enumerate() {
+            mixing_g(
+                cs.namespace(|| format!("mixing invocation {}", j + 1)),
+                &mut v,
+                words,
+                &m[s[2 * j]],
+                &m[s[2 * j + 1]],
+            )?;
+        }
+    }
+
+    (0..8)
+        .map(|i| {
+            let cs = &mut cs.namespace(|| format!("h{} ^ v{} ^ v{}", i, i, i + 8));
+            h[i].xor(cs.namespace(|| "first xor"), &v[i])?
+                .xor(cs.namespace(|| "second xor"), &v[i + 8])
+        })
+        .collect()
+}
+
+/// Witnesses the length of a preimage of up to `max_len` bytes: `after[i]` is
+/// set iff byte `i` is not part of the preimage any more.
+fn alloc_length<Scalar, CS>(
+    cs: &mut CS,
+    max_len: usize,
+    len: Option<usize>,
+) -> Result<Vec<AllocatedBit>, SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    let after = (0..=max_len)
+        .map(|i| AllocatedBit::alloc(cs.namespace(|| format!("after {}", i)), len.map(|l| i >= l)))
+        .collect::<Result<Vec<_>, _>>()?;
+    for i in 1..=max_len {
+        cs.enforce(
+            || format!("after {} implies after {}", i - 1, i),
+            |lc| lc + after[i - 1].get_variable(),
+            |lc| lc + CS::one() - after[i].get_variable(),
+            |lc| lc,
+        );
+    }
+    cs.enforce(
+        || "preimage fits",
+        |lc| lc + after[max_len].get_variable(),
+        |lc| lc + CS::one(),
+        |lc| lc + CS::one(),
+    );
+    Ok(after)
+}
+
+/// Witnesses the state after the final block, `states[final_block - 1]`, where
+/// `is_final(block)` is set for the final block (1-indexed) only.
+fn select_final<Scalar, CS>(
+    cs: &mut CS,
+    states: &[Vec<Boolean>],
+    final_block: Option<usize>,
+    is_final: impl Fn(usize) -> LinearCombination<Scalar>,
+) -> Result<Vec<Boolean>, SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    let selected = (0..states[0].len())
+        .map(|i| {
+            let value = final_block.and_then(|block| states[block - 1][i].get_value());
+            AllocatedBit::alloc(cs.namespace(|| format!("mid bit {}", i)), value).map(Boolean::from)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (block, state) in states.iter().enumerate() {
+        for (i, (bit, state_bit)) in selected.iter().zip(state).enumerate() {
+            cs.enforce(
+                || format!("select bit {} of block {}", i, block + 1),
+                |lc| lc + &is_final(block + 1),
+                |lc| lc + &bit.lc(CS::one(), Scalar::ONE) - &state_bit.lc(CS::one(), Scalar::ONE),
+                |lc| lc,
+            );
+        }
+    }
+    Ok(selected)
+}
+
+/// Witnesses `old` if `after` is set and `new` otherwise.
+fn select_num<Scalar, CS>(
     mut cs: CS,
-    data: &[Boolean],
-) -> Result<Vec<Boolean>, SynthesisError> {
-    // Flip endianness of each input byte
-    let input: Vec<_> = data
-        .chunks(8)
-        .map(|c| c.iter().rev())
-        .flatten()
-        .cloned()
-        .collect();
-
-    let mid = sha256(cs.namespace(|| "SHA-256(input)"), &input)?;
-    let res = sha256(cs.namespace(|| "SHA-256(mid)"), &mid)?;
-
-    // Flip endianness of each output byte
-    Ok(res
-        .chunks(8)
-        .map(|c| c.iter().rev())
-        .flatten()
-        .cloned()
-        .collect())
+    after: &AllocatedBit,
+    old: &AllocatedNum<Scalar>,
+    new: &AllocatedNum<Scalar>,
+) -> Result<AllocatedNum<Scalar>, SynthesisError>
+where
+    Scalar: PrimeField,
+    CS: ConstraintSystem<Scalar>,
+{
+    let selected = AllocatedNum::alloc(cs.namespace(|| "selected"), || {
+        match after.get_value().ok_or(SynthesisError::AssignmentMissing)? {
+            true => old.get_value(),
+            false => new.get_value(),
+        }
+        .ok_or(SynthesisError::AssignmentMissing)
+    })?;
+    // (1 - after) * (new - old) = selected - old
+    cs.enforce(
+        || "select",
+        |lc| lc + CS::one() - after.get_variable(),
+        |lc| lc + new.get_variable() - old.get_variable(),
+        |lc| lc + selected.get_variable() - old.get_variable(),
+    );
+    Ok(selected)
 }
 
 struct MyCircuit {
-    /// The input to SHA-256d we are proving that we know. Set to `None` when we
-    /// are verifying a proof (and do not have the witness data).
-    preimage: Option<[u8; 80]>,
+    /// Hash the circuit proves knowledge of a preimage for.
+    hash: Hash,
+    /// Number of blocks the circuit hashes. Every preimage of up to
+    /// `hash.capacity(blocks)` bytes can be proven with the same parameters.
+    blocks: usize,
+    /// The preimage we are proving that we know. Set to `None` when we are
+    /// verifying a proof (and do not have the witness data).
+    preimage: Option<Vec<u8>>,
+    /// The cell the proof is made for. Set to `None` when generating parameters.
+    binding: Option<Binding>,
 }
 
-impl<Scalar: PrimeField> Circuit<Scalar> for MyCircuit {
+impl<Scalar: PrimeFieldBits> Circuit<Scalar> for MyCircuit {
     fn synthesize<CS: ConstraintSystem<Scalar>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
-        // Compute the values for the bits of the preimage. If we are verifying a proof,
-        // we still need to create the same constraints, so we return an equivalent-size
-        // Vec of None (indicating that the value of each bit is unknown).
-        let bit_values = if let Some(preimage) = self.preimage {
-            preimage
+        // The bits of the hash, least significant bit of each byte first.
+        let hash = match self.hash {
+            Hash::Sha256d | Hash::Sha256 => self.sha256(cs)?,
+            Hash::Blake2s => self.blake2s(cs)?,
+            Hash::Mimc => self.mimc(cs)?,
+        };
+
+        // Expose the vector of 32 boolean variables as compact public inputs.
+        multipack::pack_into_inputs(cs.namespace(|| "pack hash"), &hash)?;
+
+        // Expose the binding as further public inputs. They take part in no
+        // constraint of ours, but bellman enforces `input * 0 = 0` for every
+        // input, which is enough for the proof to commit to their values.
+        let binding_inputs = (Binding::BYTES * 8).div_ceil(Scalar::CAPACITY as usize);
+        let binding_values: Vec<Option<Scalar>> = match self.binding {
+            Some(ref binding) => binding.inputs().into_iter().map(Some).collect(),
+            None => vec![None; binding_inputs],
+        };
+        assert_eq!(binding_values.len(), binding_inputs);
+        for (i, value) in binding_values.into_iter().enumerate() {
+            cs.alloc_input(
+                || format!("binding {}", i),
+                || value.ok_or(SynthesisError::AssignmentMissing),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl MyCircuit {
+    /// The circuit of SHA-256 and SHA-256d.
+    fn sha256<Scalar, CS>(&self, cs: &mut CS) -> Result<Vec<Boolean>, SynthesisError>
+    where
+        Scalar: PrimeField,
+        CS: ConstraintSystem<Scalar>,
+    {
+        let max_len = self.hash.capacity(self.blocks);
+        let len = self.preimage.as_ref().map(|p| p.len());
+
+        // Compute the values for the bits of the padded preimage, most significant
+        // bit of each byte first. If we are verifying a proof, we still need to
+        // create the same constraints, so we return an equivalent-size Vec of None
+        // (indicating that the value of each bit is unknown).
+        let bit_values = if let Some(ref preimage) = self.preimage {
+            pad(preimage, self.blocks)
                 .into_iter()
-                .map(|byte| (0..8).map(move |i| (byte >> i) & 1u8 == 1u8))
-                .flatten()
-                .map(|b| Some(b))
+                .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1u8 == 1u8))
+                .map(Some)
                 .collect()
         } else {
-            vec![None; 80 * 8]
+            vec![None; self.blocks * BLOCK_BYTES * 8]
         };
-        assert_eq!(bit_values.len(), 80 * 8);
+        assert_eq!(bit_values.len(), self.blocks * BLOCK_BYTES * 8);
 
-        // Witness the bits of the preimage.
-        let preimage_bits = bit_values
+        // Witness the bits of the padded preimage.
+        let padded_bits = bit_values
             .into_iter()
             .enumerate()
             // Allocate each bit.
@@ -69,17 +542,365 @@ impl<Scalar: PrimeField> Circuit<Scalar> for MyCircuit {
             .map(|b| b.map(Boolean::from))
             .collect::<Result<Vec<_>, _>>()?;
 
-        // Compute hash = SHA-256d(preimage).
-        let hash = sha256d(cs.namespace(|| "SHA-256d(preimage)"), &preimage_bits)?;
+        // Witness the length of the preimage.
+        let after = alloc_length(cs, max_len, len)?;
 
-        // Expose the vector of 32 boolean variables as compact public inputs.
-        multipack::pack_into_inputs(cs.namespace(|| "pack hash"), &hash)
+        // Every byte from the end of the preimage on, as a linear combination.
+        let after_lc = |i: usize| -> LinearCombination<Scalar> {
+            if i <= max_len {
+                LinearCombination::zero() + after[i].get_variable()
+            } else {
+                LinearCombination::zero() + CS::one()
+            }
+        };
+        // The byte right after the preimage, where the padding starts.
+        let end_lc = |i: usize| -> LinearCombination<Scalar> {
+            match i {
+                0 => after_lc(0),
+                i if i <= max_len => after_lc(i) - &after_lc(i - 1),
+                _ => LinearCombination::zero(),
+            }
+        };
+        // Whether the preimage ends within the first `block` blocks.
+        let ends_by = |block: usize| -> LinearCombination<Scalar> {
+            if block == 0 {
+                LinearCombination::zero()
+            } else {
+                after_lc(self.hash.capacity(block))
+            }
+        };
+        // Whether the padding ends in `block` (1-indexed).
+        let is_final = |block: usize| ends_by(block) - &ends_by(block - 1);
+        // The length of the preimage in bits.
+        let bit_len = (0..max_len).fold(LinearCombination::zero(), |lc, i| {
+            lc + (Scalar::from(8), CS::one()) - (Scalar::from(8), after[i].get_variable())
+        });
+
+        // Enforce the padding: a single 1 bit after the preimage followed by zeros
+        // up to the 64-bit length field of the final block. Bytes in the length
+        // field of the final block and in the blocks after it are left alone here.
+        for (i, byte) in padded_bits.chunks(8).enumerate() {
+            let length_field_of =
+                (i % BLOCK_BYTES >= BLOCK_BYTES - 8).then_some(i / BLOCK_BYTES + 1);
+            let zero_from = match length_field_of {
+                Some(block) => after_lc(i) - &ends_by(block),
+                None => after_lc(i),
+            };
+            for (j, bit) in byte.iter().enumerate() {
+                let expected = if j == 0 {
+                    end_lc(i)
+                } else {
+                    LinearCombination::zero()
+                };
+                cs.enforce(
+                    || format!("padding bit {}", i * 8 + j),
+                    |lc| lc + &zero_from,
+                    |lc| lc + &bit.lc(CS::one(), Scalar::ONE) - &expected,
+                    |lc| lc,
+                );
+            }
+        }
+
+        // The length field of the final block holds the length of the preimage.
+        for block in 1..=self.blocks {
+            let field = &padded_bits[(block * BLOCK_BYTES - 8) * 8..block * BLOCK_BYTES * 8];
+            let field_lc = field
+                .iter()
+                .rev()
+                .enumerate()
+                .fold(LinearCombination::zero(), |lc, (i, bit)| {
+                    lc + &bit.lc(CS::one(), Scalar::from(1u64 << i))
+                });
+            cs.enforce(
+                || format!("length field of block {}", block),
+                |lc| lc + &is_final(block),
+                |lc| lc + &field_lc - &bit_len,
+                |lc| lc,
+            );
+        }
+
+        // Run the compression function over every block, keeping the state after each.
+        let mut cur: Vec<UInt32> = IV.iter().map(|&v| UInt32::constant(v)).collect();
+        let mut states = Vec::with_capacity(self.blocks);
+        for (i, block) in padded_bits.chunks(BLOCK_BYTES * 8).enumerate() {
+            cur = sha256_compression(cs.namespace(|| format!("block {}", i)), block, &cur)?;
+            states.push(
+                cur.iter()
+                    .flat_map(|word| word.clone().into_bits_be())
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        // Pick the state after the final block: that is SHA-256(preimage).
+        let final_block = len.map(|len| self.hash.blocks_for(len));
+        let mid = select_final(cs, &states, final_block, is_final)?;
+
+        // Compute hash = SHA-256(SHA-256(preimage)) for SHA-256d.
+        let hash = match self.hash {
+            Hash::Sha256d => sha256(cs.namespace(|| "SHA-256(mid)"), &mid)?,
+            _ => mid,
+        };
+
+        // Flip endianness of each output byte.
+        Ok(hash
+            .chunks(8)
+            .flat_map(|c| c.iter().rev())
+            .cloned()
+            .collect())
+    }
+
+    /// The circuit of BLAKE2s.
+    fn blake2s<Scalar, CS>(&self, cs: &mut CS) -> Result<Vec<Boolean>, SynthesisError>
+    where
+        Scalar: PrimeField,
+        CS: ConstraintSystem<Scalar>,
+    {
+        let max_len = self.hash.capacity(self.blocks);
+        let len = self.preimage.as_ref().map(|p| p.len());
+
+        // Witness the bits of the preimage followed by zeros up to `max_len`,
+        // least significant bit of each byte first as BLAKE2s reads the message
+        // as little-endian words.
+        let bit_values = match self.preimage {
+            Some(ref preimage) => {
+                let mut padded = preimage.clone();
+                padded.resize(max_len, 0);
+                padded
+                    .into_iter()
+                    .flat_map(|byte| (0..8).map(move |i| (byte >> i) & 1u8 == 1u8))
+                    .map(Some)
+                    .collect()
+            }
+            None => vec![None; max_len * 8],
+        };
+        let bits = bit_values
+            .into_iter()
+            .enumerate()
+            .map(|(i, b)| AllocatedBit::alloc(cs.namespace(|| format!("preimage bit {}", i)), b))
+            .map(|b| b.map(Boolean::from))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Witness the length of the preimage. The bytes after it are zero.
+        let after = alloc_length(cs, max_len, len)?;
+        for (i, byte) in bits.chunks(8).enumerate() {
+            for (j, bit) in byte.iter().enumerate() {
+                cs.enforce(
+                    || format!("zero bit {}", i * 8 + j),
+                    |lc| lc + after[i].get_variable(),
+                    |lc| lc + &bit.lc(CS::one(), Scalar::ONE),
+                    |lc| lc,
+                );
+            }
+        }
+
+        // Whether the preimage ends within the first `block` blocks.
+        let ends_by = |block: usize| -> LinearCombination<Scalar> {
+            if block == 0 {
+                LinearCombination::zero()
+            } else {
+                LinearCombination::zero() + after[block * BLOCK_BYTES].get_variable()
+            }
+        };
+        // Whether `block` (1-indexed) is the final block.
+        let is_final = |block: usize| ends_by(block) - &ends_by(block - 1);
+        // The length of the preimage in bytes.
+        let byte_len = (0..max_len).fold(LinearCombination::zero(), |lc, i| {
+            lc + CS::one() - after[i].get_variable()
+        });
+
+        // The parameter block: a 32 byte digest without key.
+        let mut cur: Vec<UInt32> = IV.iter().map(|&v| UInt32::constant(v)).collect();
+        cur[0] = UInt32::constant(IV[0] ^ 0x0101_0000 ^ 32);
+
+        // Run the compression function over every block, keeping the state after each.
+        let final_block = len.map(|len| self.hash.blocks_for(len));
+        let mut states = Vec::with_capacity(self.blocks);
+        for (i, block) in bits.chunks(BLOCK_BYTES * 8).enumerate() {
+            let cs = &mut cs.namespace(|| format!("block {}", i));
+            let last = AllocatedBit::alloc(
+                cs.namespace(|| "final"),
+                final_block.map(|block| block == i + 1),
+            )?;
+            cs.enforce(
+                || "final block",
+                |lc| lc + last.get_variable() - &is_final(i + 1),
+                |lc| lc + CS::one(),
+                |lc| lc,
+            );
+
+            // The byte counter is the length of the preimage in the final block
+            // and the number of bytes up to the end of the block otherwise.
+            let offset = (BLOCK_BYTES * (i + 1)) as u64;
+            let counter = UInt32::alloc(
+                cs.namespace(|| "counter"),
+                len.map(|len| match final_block == Some(i + 1) {
+                    true => len as u32,
+                    false => offset as u32,
+                }),
+            )?;
+            let counter_lc = counter
+                .clone()
+                .into_bits()
+                .iter()
+                .enumerate()
+                .fold(LinearCombination::zero(), |lc, (k, bit)| {
+                    lc + &bit.lc(CS::one(), Scalar::from(1u64 << k))
+                });
+            cs.enforce(
+                || "counter value",
+                |lc| lc + &is_final(i + 1),
+                |lc| lc + &byte_len - (Scalar::from(offset), CS::one()),
+                |lc| lc + &counter_lc - (Scalar::from(offset), CS::one()),
+            );
+
+            let words: Vec<UInt32> = block.chunks(32).map(UInt32::from_bits).collect();
+            cur = blake2s_compression(
+                cs.namespace(|| "compression"),
+                &cur,
+                &words,
+                &counter,
+                &Boolean::from(last),
+            )?;
+            states.push(
+                cur.iter()
+                    .flat_map(|word| word.clone().into_bits())
+                    .collect::<Vec<_>>(),
+            );
+        }
+
+        // The state after the final block is the hash, already in little-endian
+        // bytes of little-endian bits.
+        select_final(cs, &states, final_block, is_final)
+    }
+
+    /// The circuit of MiMC.
+    fn mimc<Scalar, CS>(&self, cs: &mut CS) -> Result<Vec<Boolean>, SynthesisError>
+    where
+        Scalar: PrimeFieldBits,
+        CS: ConstraintSystem<Scalar>,
+    {
+        let max_len = self.hash.capacity(self.blocks);
+        let len = self.preimage.as_ref().map(|p| p.len());
+        // The preimage followed by zeros up to `max_len`.
+        let padded = self.preimage.as_ref().map(|preimage| {
+            let mut padded = preimage.clone();
+            padded.resize(max_len, 0);
+            padded
+        });
+
+        // Witness the bits of the padded preimage, least significant bit of each
+        // byte first.
+        let bit_values = match padded {
+            Some(ref padded) => padded
+                .iter()
+                .flat_map(|&byte| (0..8).map(move |i| (byte >> i) & 1u8 == 1u8))
+                .map(Some)
+                .collect(),
+            None => vec![None; max_len * 8],
+        };
+        let bits = bit_values
+            .into_iter()
+            .enumerate()
+            .map(|(i, b)| AllocatedBit::alloc(cs.namespace(|| format!("preimage bit {}", i)), b))
+            .map(|b| b.map(Boolean::from))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Witness the length of the preimage. The bytes after it are zero.
+        let after = alloc_length(cs, max_len, len)?;
+        for (i, byte) in bits.chunks(8).enumerate() {
+            for (j, bit) in byte.iter().enumerate() {
+                cs.enforce(
+                    || format!("zero bit {}", i * 8 + j),
+                    |lc| lc + after[i].get_variable(),
+                    |lc| lc + &bit.lc(CS::one(), Scalar::ONE),
+                    |lc| lc,
+                );
+            }
+        }
+        // The length of the preimage in bytes.
+        let byte_len = (0..max_len).fold(LinearCombination::zero(), |lc, i| {
+            lc + CS::one() - after[i].get_variable()
+        });
+
+        // The state starts as (0, length).
+        let constants = mimc::constants::<Scalar>();
+        let mut xl: Option<AllocatedNum<Scalar>> = None;
+        let mut xr = AllocatedNum::alloc(cs.namespace(|| "length"), || {
+            len.map(|len| Scalar::from(len as u64))
+                .ok_or(SynthesisError::AssignmentMissing)
+        })?;
+        cs.enforce(
+            || "length value",
+            |lc| lc + xr.get_variable(),
+            |lc| lc + CS::one(),
+            |lc| lc + &byte_len,
+        );
+
+        // Absorb every chunk up to the end of the preimage, at least one.
+        let chunk_bits = mimc::CHUNK_BYTES * 8;
+        for (i, chunk) in bits.chunks(chunk_bits).enumerate() {
+            let cs = &mut cs.namespace(|| format!("chunk {}", i));
+            let start = i * mimc::CHUNK_BYTES;
+
+            let chunk_lc = chunk
+                .iter()
+                .enumerate()
+                .fold(LinearCombination::zero(), |lc, (k, bit)| {
+                    lc + &bit.lc(CS::one(), Scalar::from(2).pow_vartime([k as u64]))
+                });
+            let chunk_value = padded
+                .as_ref()
+                .map(|padded| mimc::chunk::<Scalar>(&padded[start..start + mimc::CHUNK_BYTES]));
+            let previous = match &xl {
+                Some(xl) => xl.get_value(),
+                None => Some(Scalar::ZERO),
+            };
+            let absorbed = AllocatedNum::alloc(cs.namespace(|| "absorbed"), || {
+                Ok(previous.ok_or(SynthesisError::AssignmentMissing)?
+                    + chunk_value.ok_or(SynthesisError::AssignmentMissing)?)
+            })?;
+            cs.enforce(
+                || "absorb",
+                |lc| match &xl {
+                    Some(xl) => lc + xl.get_variable() + &chunk_lc,
+                    None => lc + &chunk_lc,
+                },
+                |lc| lc + CS::one(),
+                |lc| lc + absorbed.get_variable(),
+            );
+            let (new_xl, new_xr) =
+                mimc::permute_gadget(cs.namespace(|| "permute"), &absorbed, &xr, &constants)?;
+
+            // Chunks after the end of the preimage leave the state as it is.
+            (xl, xr) = match xl {
+                None => (Some(new_xl), new_xr),
+                Some(old_xl) => {
+                    let after = after[start].clone();
+                    (
+                        Some(select_num(cs.namespace(|| "xL"), &after, &old_xl, &new_xl)?),
+                        select_num(cs.namespace(|| "xR"), &after, &xr, &new_xr)?,
+                    )
+                }
+            };
+        }
+
+        // The hash is xL in little-endian bits, padded to 256 bits.
+        let mut hash = xl
+            .expect("there is at least one chunk")
+            .to_bits_le_strict(cs.namespace(|| "hash bits"))?;
+        hash.resize(256, Boolean::constant(false));
+        Ok(hash)
     }
 }
 
-pub fn setup() -> (Parameters<Bls12>, PreparedVerifyingKey<Bls12>) {
+pub fn setup(hash: Hash, blocks: usize) -> (Parameters<Bls12>, PreparedVerifyingKey<Bls12>) {
     let params = {
-        let c = MyCircuit { preimage: None };
+        let c = MyCircuit {
+            hash,
+            blocks,
+            preimage: None,
+            binding: None,
+        };
         groth16::generate_random_parameters::<Bls12, _, _>(c, &mut OsRng).unwrap()
     };
 
@@ -88,58 +909,168 @@ pub fn setup() -> (Parameters<Bls12>, PreparedVerifyingKey<Bls12>) {
     (params, pvk)
 }
 
-pub fn prove(params: Parameters<Bls12>, m: String) -> Proof<Bls12> {
-    // Pick a preimage and compute its hash.
-    let mut preimage: [u8; 80] = [0; 80];
-    preimage[..m.len()].copy_from_slice(m.as_bytes());
+/// Proves knowledge of `m` for the cell `binding` with parameters generated by
+/// `setup(hash, blocks)`. `m` must not be longer than `hash.capacity(blocks)`.
+pub fn prove(
+    params: &Parameters<Bls12>,
+    hash: Hash,
+    blocks: usize,
+    m: &[u8],
+    binding: &Binding,
+) -> Proof<Bls12> {
+    assert!(m.len() <= hash.capacity(blocks));
 
     // Create an instance of our circuit (with the preimage as a witness).
     let c = MyCircuit {
-        preimage: Some(preimage),
+        hash,
+        blocks,
+        preimage: Some(m.to_vec()),
+        binding: Some(binding.clone()),
     };
 
     // Create a Groth16 proof with our parameters.
-    let proof = groth16::create_random_proof(c, &params, &mut OsRng).unwrap();
-    proof
+    groth16::create_random_proof(c, params, &mut OsRng).unwrap()
 }
 
-pub fn verify(pvk: &PreparedVerifyingKey<Bls12>, hash: &[u8], proof: &Proof<Bls12>) -> bool {
-    // Pack the hash as inputs for proof verification.
-    let hash_bits = multipack::bytes_to_bits_le(&hash);
-    let inputs = multipack::compute_multipacking(&hash_bits);
+pub fn verify(
+    pvk: &PreparedVerifyingKey<Bls12>,
+    hash: &[u8],
+    binding: &Binding,
+    proof: &Proof<Bls12>,
+) -> bool {
+    // Pack the hash and the binding as inputs for proof verification.
+    let hash_bits = multipack::bytes_to_bits_le(hash);
+    let mut inputs = multipack::compute_multipacking(&hash_bits);
+    inputs.extend(binding.inputs::<bls12_381::Scalar>());
 
-    if groth16::verify_proof(pvk, &proof, &inputs).is_ok() {
-        return true;
-    } else {
-        return false;
-    }
+    groth16::verify_proof(pvk, proof, &inputs).is_ok()
 }
 
 // `cargo test -- --nocapture` でテスト内の println! を画面に出力
 #[cfg(test)]
 mod test {
     use super::*;
-    use sha2::{Digest, Sha256};
+    use bellman::gadgets::test::TestConstraintSystem;
+    use bls12_381::Scalar;
+    use ff::Field;
+
     #[test]
     fn test_prove() {
-        let m = String::from("2rL9AkP0zS6E8yYXg7lUvB1tjHn4JwQcOqT5IeWxN3MmKpFfDdVbGhZaC");
-        let mut preimage: [u8; 80] = [0; 80];
-        preimage[..m.len()].copy_from_slice(m.as_bytes());
-        let hash = Sha256::digest(&Sha256::digest(&preimage));
+        let m = String::from(
+            "\"https://example.com/search?q=zk4log&lang=ja&session=2rL9AkP0zS6E8yYXg7lUvB1tjHn4J\"wQcOqT5IeWxN3MmK",
+        );
+        assert!(m.len() > 80);
+        let hash = Hash::Sha256d.digest(m.as_bytes());
+        let binding = Binding {
+            log_id: "test".to_string(),
+            record: 7,
+            column: "url".to_string(),
+            commitment: [0; commitment::BYTES],
+        };
 
-        let (params, pvk) = setup();
-        let proof = prove(params, m);
-        verify(&pvk, &hash, &proof);
+        let blocks = Hash::Sha256d.blocks_for(m.len());
+        let (params, pvk) = setup(Hash::Sha256d, blocks);
+        let proof = prove(&params, Hash::Sha256d, blocks, m.as_bytes(), &binding);
+        assert!(verify(&pvk, &hash, &binding, &proof));
+        assert!(!verify(
+            &pvk,
+            &Hash::Sha256d.digest(b"other"),
+            &binding,
+            &proof
+        ));
+
+        // The same proof must not verify for any other cell.
+        let other_record = Binding {
+            record: 8,
+            ..binding.clone()
+        };
+        let other_column = Binding {
+            column: "referer".to_string(),
+            ..binding.clone()
+        };
+        let other_log = Binding {
+            log_id: "other".to_string(),
+            ..binding.clone()
+        };
+        let other_commitment = Binding {
+            commitment: [1; commitment::BYTES],
+            ..binding.clone()
+        };
+        for other in [other_record, other_column, other_log, other_commitment] {
+            assert!(!verify(&pvk, &hash, &other, &proof));
+        }
 
         println!("{:?}", proof);
     }
 
+    #[test]
+    fn test_circuit_lengths() {
+        let blocks = 3;
+        let binding = Binding {
+            log_id: "test".to_string(),
+            record: 0,
+            column: "msg".to_string(),
+            commitment: [0; commitment::BYTES],
+        };
+        for hash in [Hash::Sha256d, Hash::Sha256, Hash::Blake2s, Hash::Mimc] {
+            let capacity = hash.capacity(blocks);
+            let lengths = [
+                0, 1, 30, 31, 32, 55, 56, 63, 64, 65, 119, 120, 128, capacity,
+            ];
+            for len in lengths.into_iter().filter(|&len| len <= capacity) {
+                let m: Vec<u8> = (0..len).map(|i| b'A' + (i % 26) as u8).collect();
+                let mut cs = TestConstraintSystem::<Scalar>::new();
+                let c = MyCircuit {
+                    hash,
+                    blocks,
+                    preimage: Some(m.clone()),
+                    binding: Some(binding.clone()),
+                };
+                c.synthesize(&mut cs).unwrap();
+                assert!(
+                    cs.is_satisfied(),
+                    "{} len {}: {:?}",
+                    hash.name(),
+                    len,
+                    cs.which_is_unsatisfied()
+                );
+
+                let hash_bits = multipack::bytes_to_bits_le(&hash.digest(&m));
+                let mut inputs = multipack::compute_multipacking(&hash_bits);
+                inputs.extend(binding.inputs::<Scalar>());
+                assert!(cs.verify(&inputs), "{} len {}", hash.name(), len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_circuit_rejects_bad_padding() {
+        let mut cs = TestConstraintSystem::<Scalar>::new();
+        let c = MyCircuit {
+            hash: Hash::Sha256d,
+            blocks: 2,
+            preimage: Some(b"zk4log".to_vec()),
+            binding: Some(Binding {
+                log_id: "test".to_string(),
+                record: 0,
+                column: "user".to_string(),
+                commitment: [0; commitment::BYTES],
+            }),
+        };
+        c.synthesize(&mut cs).unwrap();
+        assert!(cs.is_satisfied());
+
+        // Claim the preimage is one byte shorter than it really is.
+        cs.set("after 5/boolean", Scalar::ONE);
+        assert!(!cs.is_satisfied());
+    }
+
     #[test]
     fn sha256_test() {
         let preimage = [65; 80];
         println!(
             "{}",
-            &Sha256::digest(&Sha256::digest(&preimage))
+            &Sha256::digest(Sha256::digest(preimage))
                 .iter()
                 .map(|b| format!("{:02x}", b))
                 .collect::<Vec<String>>()
@@ -1,200 +1,544 @@
+use crate::access::LogFormat;
+use crate::bundle::{self, Bundle, Manifest, TempDir};
+use crate::columns;
+use crate::commitment::{self, Chain};
+use crate::keys;
+use crate::opening::{OpenedCell, Opening};
+use crate::path;
+use crate::policy::Policy;
 use crate::progress_bar::ProgressBar;
-use crate::zk::{self, prove};
-use bellman::groth16::{self, PreparedVerifyingKey, Proof, VerifyingKey};
+use crate::proof_file::{self, Entry, Header, Item, ProofWriter, RecordCommitment};
+use crate::records::{self, Format, RecordWriter};
+use crate::scan;
+use crate::signing;
+use crate::vault::{Vault, VaultKey, VaultWriter};
+use crate::zk::{self, prove, Binding};
+use bellman::groth16::{self, PreparedVerifyingKey, Proof};
 use bls12_381::Bls12;
 use crossterm::style::Stylize;
-use dialoguer::{theme::ColorfulTheme, MultiSelect};
-use hex;
+use dialoguer::{theme::ColorfulTheme, MultiSelect, Password};
 use nu_path::expand_tilde;
 use nu_plugin::{EvaluatedCall, LabeledError};
-use nu_protocol::Value;
-use rand::Rng;
-use serde_json::{from_str, to_value, Map};
+use nu_protocol::{Span, Value};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use sha2::{Digest, Sha256};
-use std::{
-    fs,
-    io::{self, Read, Write},
-    process::Command,
-};
+use std::{fs, io};
+
+/// `zk4log keygen` のデフォルトのブロック数 (ソルト込みで 247 バイトまで)
+const DEFAULT_BLOCKS: i64 = 4;
+
+/// ソルトの保管庫のパスフレーズを渡す環境変数
+const VAULT_PASSPHRASE_ENV: &str = "ZK4LOG_VAULT_PASSPHRASE";
 
 pub struct Zk4log;
 
+/// 秘匿化・検証するログ。ファイルは1レコードずつ読み、
+/// パイプラインから渡されたテーブルはそのまま使う
+enum Log {
+    File { path: String, format: Format },
+    Table(Vec<serde_json::Value>),
+}
+
+type LogRecords<'a> = Box<dyn Iterator<Item = Result<serde_json::Value, LabeledError>> + 'a>;
+
+impl Log {
+    // new: ログファイルが指定されていればそれを、なければパイプラインのテーブルを使う
+    fn new(path: Option<String>, call: &EvaluatedCall, input: &Value) -> Result<Log, LabeledError> {
+        if let Some(path) = path {
+            let path = Zk4log::expand_tilde_and_check_file_exists(&path, call)?;
+            let format = Zk4log::log_format(call, &path)?;
+            return Ok(Log::File { path, format });
+        }
+
+        let rows = match input {
+            Value::List { vals, .. } => vals.iter().collect(),
+            Value::Record { .. } => vec![input],
+            _ => {
+                return Err(LabeledError {
+                    label: "No log given".into(),
+                    msg: "give a log file or pipe a table into the command".into(),
+                    span: Some(call.head),
+                })
+            }
+        };
+        let table = rows
+            .into_iter()
+            .enumerate()
+            .map(|(idx, row)| match row {
+                Value::Record { .. } => Zk4log::json_value(row),
+                _ => Err(format!("row {} is not a record", idx)),
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|e| LabeledError {
+                label: "Invalid input table".into(),
+                msg: e,
+                span: Some(call.head),
+            })?;
+        Ok(Log::Table(table))
+    }
+
+    fn records(&self, call: &EvaluatedCall) -> Result<LogRecords<'_>, LabeledError> {
+        match self {
+            Log::File { path, format } => Ok(Box::new(Zk4log::records(path, format, call)?)),
+            Log::Table(table) => Ok(Box::new(table.iter().cloned().map(Ok))),
+        }
+    }
+}
+
 impl Zk4log {
-    pub fn hide(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
+    pub fn hide(&self, call: &EvaluatedCall, input: &Value) -> Result<Value, LabeledError> {
         let mut item_count = 1;
         // 引数の文字列を取得する
-        let path: String = call.req(0)?;
-        let output = call.get_flag("output")?;
+        let path: Option<String> = call.opt(0)?;
+        let output: Option<String> = call.get_flag("output")?;
+        let proof_name: Option<String> = call.get_flag("proof")?;
+        let bundle_name: Option<String> = call.get_flag("bundle")?;
+        let params_path: Option<String> = call.get_flag("params")?;
+        let hash_flag = Self::hash_flag(call)?;
+        let log_id: String = call.get_flag("log-id")?.unwrap_or_else(Self::gen_log_id);
+        let salt_mode: String = call
+            .get_flag("salt-mode")?
+            .unwrap_or_else(|| "cell".to_string());
+        if salt_mode != "cell" && salt_mode != "log" {
+            return Err(LabeledError {
+                label: "Invalid salt mode".into(),
+                msg: "--salt-mode must be \"cell\" or \"log\"".into(),
+                span: Some(call.head),
+            });
+        }
+        let vault_key = Self::vault_key(call)?;
+        let write_error = |path: &str, e: io::Error| LabeledError {
+            label: "Unable to write file".into(),
+            msg: format!("failed to write {}: {}", path, e),
+            span: Some(call.head),
+        };
 
-        // pathのファイルが存在するかどうかを確認する
-        let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
-        eprintln!("Open file: {}", path);
-
-        let data = fs::read_to_string(path).expect("Unable to read file");
-        let json_value: serde_json::Value =
-            serde_json::from_str(&data).expect("Invalid JSON format");
-        let mut json_datas: Vec<serde_json::Value> = Vec::new();
-
-        match json_value {
-            serde_json::Value::Array(array_val) => {
-                for item in array_val.iter() {
-                    if let Some(obj) = item.as_object() {
-                        json_datas.push(serde_json::Value::Object(obj.clone()));
-                    } else {
-                        return Err(LabeledError {
-                            label: "Invalid JSON format".into(),
-                            msg: "Invalid JSON format".into(),
-                            span: Some(call.head),
-                        });
-                    }
-                }
+        // --bundle があれば、秘匿化したログ、proof、鍵を一時ディレクトリに書き出してから
+        // 1つのバンドルにまとめる
+        let staging = match &bundle_name {
+            Some(_) if output.is_some() || proof_name.is_some() => {
+                return Err(LabeledError {
+                    label: "Conflicting output".into(),
+                    msg: "--bundle cannot be combined with --output or --proof".into(),
+                    span: Some(call.head),
+                })
             }
-            serde_json::Value::Object(obj) => {
-                json_datas.push(serde_json::Value::Object(obj));
+            Some(bundle_name) => Some(TempDir::new().map_err(|e| write_error(bundle_name, e))?),
+            None => None,
+        };
+        let proof_name = match &staging {
+            Some(staging) => staging.path(bundle::PROOF),
+            None => proof_name.unwrap_or_else(|| "out.proof".to_string()),
+        };
+        // --sign-key があれば、どのログ収集者が秘匿化したかを示すためにバンドルに署名する
+        let sign_key = match call.get_flag::<String>("sign-key")? {
+            Some(_) if bundle_name.is_none() => {
+                return Err(LabeledError {
+                    label: "Missing --bundle".into(),
+                    msg: "--sign-key signs a bundle, give --bundle as well".into(),
+                    span: Some(call.head),
+                })
             }
-            _ => {
+            Some(path) => {
+                let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
+                Some(signing::read_signing_key(&path).map_err(|e| LabeledError {
+                    label: "Invalid signing key".into(),
+                    msg: format!("{}: {}", path, e),
+                    span: Some(call.head),
+                })?)
+            }
+            None => None,
+        };
+
+        // ログファイルが指定されていなければ、パイプラインのテーブルを秘匿化する
+        let log = Log::new(path, call, input)?;
+
+        // 秘匿化したログは入力と同じ形式で書き出す
+        // (テーブルは秘匿化したテーブルを返すので、バンドルにするときだけ書き出す)
+        let output_format = match &log {
+            Log::File { path, format } => {
+                eprintln!("Open file: {}", path);
+                format.output()
+            }
+            Log::Table(_) => Format::Ndjson,
+        };
+        let output_name = match (&log, &staging) {
+            (_, Some(staging)) => Some(staging.path(&format!("log.{}", output_format.extension()))),
+            (Log::File { .. }, None) => {
+                Some(output.unwrap_or_else(|| format!("output.{}", output_format.extension())))
+            }
+            (Log::Table(_), None) if output.is_some() => {
                 return Err(LabeledError {
-                    label: "Invalid JSON format".into(),
-                    msg: "Invalid JSON format".into(),
+                    label: "Unexpected --output".into(),
+                    msg: "the hidden table is returned; save it with `| save <file>`".into(),
                     span: Some(call.head),
-                });
+                })
             }
-        }
+            (Log::Table(_), None) => None,
+        };
+        let vault_name: String =
+            call.get_flag("vault")?
+                .unwrap_or_else(|| match (&bundle_name, &output_name) {
+                    (Some(bundle_name), _) => format!("{}.vault", bundle_name),
+                    (None, Some(output_name)) => format!("{}.vault", output_name),
+                    (None, None) => "out.vault".to_string(),
+                });
 
+        // 大きなログもメモリに載せずに済むよう、レコードは1つずつ読み込む。
+        // 1周目ではカラムの一覧と、個人情報の検出に使う先頭のレコードを集める
+        // (ネストされた値は user.ip のようなパスをカラム名とする)
         let mut map_keys: Vec<String> = Vec::new();
-        for json_data in json_datas.iter() {
-            if let serde_json::Value::Object(ref map) = json_data {
-                for key in map.keys() {
-                    if !map_keys.contains(key) {
-                        map_keys.push(key.clone());
-                    }
+        let mut samples: Vec<serde_json::Value> = Vec::new();
+        for json_data in log.records(call)? {
+            let json_data = json_data?;
+            for (key, _) in path::leaves(&json_data) {
+                if !map_keys.contains(&key) {
+                    map_keys.push(key);
                 }
             }
+            if samples.len() < scan::DEFAULT_SAMPLE {
+                samples.push(json_data);
+            }
         }
 
-        let selections = MultiSelect::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select the columns you want to sha256 hash")
-            .items(&map_keys)
-            .interact()
-            .unwrap();
+        // --policy があればポリシーのルールに従い、なければカラムを選ばせる
+        let policy = Self::read_policy(call)?;
+        if policy.is_some() && (call.has_flag("columns") || call.has_flag("all-except")) {
+            return Err(LabeledError {
+                label: "Conflicting column selection".into(),
+                msg: "--policy cannot be combined with --columns or --all-except".into(),
+                span: Some(call.head),
+            });
+        }
+        let selections = if policy.is_some() {
+            Vec::new()
+        } else {
+            // 個人情報らしい値を含むカラムは最初から選択しておく
+            let findings = scan::scan(&samples, scan::DEFAULT_SAMPLE);
+            let pii: Vec<Vec<scan::Kind>> = map_keys
+                .iter()
+                .map(|key| {
+                    findings
+                        .iter()
+                        .filter(|f| &f.column == key)
+                        .map(|f| f.kind)
+                        .collect()
+                })
+                .collect();
+            Self::select_columns(
+                call,
+                &map_keys,
+                &pii,
+                "Select the columns you want to sha256 hash",
+            )?
+        };
 
         // map_keysのインデックスを素早く取得するためのハッシュマップを作成
         let mut map_keys_hashmap: std::collections::HashMap<String, usize> =
             std::collections::HashMap::new();
-        for i in 0..map_keys.len() {
-            map_keys_hashmap.insert(map_keys.get(i).unwrap().clone(), i);
+        for (i, key) in map_keys.iter().enumerate() {
+            map_keys_hashmap.insert(key.clone(), i);
         }
 
-        // map_keysのインデックスを素早く取得するためのハッシュマップを作成
-        let mut map_keys_hashmap: std::collections::HashMap<String, usize> =
-            std::collections::HashMap::new();
-        for i in 0..map_keys.len() {
-            map_keys_hashmap.insert(map_keys.get(i).unwrap().clone(), i);
-        }
-
-        // 秘匿化するitemの数を数える
-        for json_data in json_datas.iter() {
-            if let serde_json::Value::Object(ref map) = json_data {
-                for i in 0..map.len() {
-                    let key = map.keys().nth(i).unwrap();
-                    let index = map_keys_hashmap.get(key).unwrap();
-                    if selections.contains(&index) {
-                        item_count += 1;
+        // --params があれば keygen で作った鍵を使う
+        // (鍵はハッシュごとの回路に対して作られるので、カンマ区切りで複数指定できる)
+        let mut loaded_params = Vec::new();
+        for params_path in params_path.iter().flat_map(|paths| paths.split(',')) {
+            let params_path = Self::expand_tilde_and_check_file_exists(params_path.trim(), call)?;
+            eprint!("loading keys...");
+            let (hash, blocks, params) =
+                keys::read_params(&params_path).map_err(|e| LabeledError {
+                    label: "Invalid params file".into(),
+                    msg: format!("failed to load {}: {}", params_path, e),
+                    span: Some(call.head),
+                })?;
+            eprintln!("\r{}", "Finished loading keys!".green());
+            loaded_params.push((params_path, hash, blocks, params));
+        }
+        // ポリシーのルールがハッシュを指定しないセルは、--hash
+        // (なければ最初の --params のハッシュ) で秘匿化する
+        let default_hash = hash_flag
+            .or(loaded_params.first().map(|(_, hash, _, _)| *hash))
+            .unwrap_or_default();
+
+        // hash_for: セルを秘匿化するハッシュ (秘匿化しないセルは None)
+        let hash_for = |key: &String, value: &serde_json::Value| match &policy {
+            Some(policy) => policy
+                .rule_for(key, value)
+                .map(|rule| rule.hash.unwrap_or(default_hash)),
+            None => selections
+                .contains(map_keys_hashmap.get(key).unwrap())
+                .then_some(default_hash),
+        };
+
+        // ソルトはセルごと (--salt-mode cell) またはログ全体で1つ (--salt-mode log)
+        // (セルごとのソルトは、ログを読み直しても同じになるよう乱数の種から導く)
+        let log_salt: String = Self::gen_salt(&mut rand::thread_rng());
+        let salt_seed: [u8; 32] = rand::thread_rng().gen();
+
+        // 2周目: 秘匿化するitemの数と、ハッシュごとに回路が扱う必要のある最長の入力を求める
+        // (秘匿化したカラムは proof ファイルのヘッダに記録する)
+        let mut max_preimage_lens: Vec<(zk::Hash, usize)> = Vec::new();
+        let mut hidden_columns: Vec<String> = Vec::new();
+        for json_data in log.records(call)? {
+            let json_data = json_data?;
+            for (key, value) in path::leaves(&json_data) {
+                if let Some(hash) = hash_for(&key, value) {
+                    item_count += 1;
+                    if !hidden_columns.contains(&key) {
+                        hidden_columns.push(key.clone());
+                    }
+                    let preimage_len = Self::preimage(value, &log_salt).len();
+                    match max_preimage_lens.iter_mut().find(|(h, _)| *h == hash) {
+                        Some((_, max)) => *max = (*max).max(preimage_len),
+                        None => max_preimage_lens.push((hash, preimage_len)),
                     }
                 }
             }
         }
 
-        let mut new_json_datas: Vec<serde_json::Value> = Vec::new();
-
-        let salt: String = Self::gen_salt();
-        fs::write("out.proof", "").unwrap(); // clear
+        // 秘匿化に使うハッシュごとに、--params の鍵を使うか、その場で鍵を生成する
+        let mut provers = Vec::new();
+        for (hash, max_preimage_len) in max_preimage_lens {
+            if params_path.is_none() {
+                eprint!("making {} keys...", hash.name());
+                let blocks = hash.blocks_for(max_preimage_len);
+                let (params, _pvk) = zk::setup(hash, blocks);
+                eprintln!("\r{}", "Finished making keys!".green());
+                provers.push((hash, blocks, params));
+                continue;
+            }
 
-        eprint!("{}", "making keys...");
-        let (params, _pvk) = zk::setup();
-        eprintln!("\r{}", "Finished making keys!".green());
+            let Some(i) = loaded_params.iter().position(|(_, h, _, _)| *h == hash) else {
+                return Err(LabeledError {
+                    label: "Missing params".into(),
+                    msg: format!(
+                        "cells are hidden with {} but no --params file was generated for it; \
+                         generate one with `zk4log keygen --hash {}`",
+                        hash.name(),
+                        hash.name()
+                    ),
+                    span: Some(call.head),
+                });
+            };
+            let (params_path, _, blocks, params) = loaded_params.swap_remove(i);
+            if max_preimage_len > hash.capacity(blocks) {
+                return Err(LabeledError {
+                    label: "Log value too long for params".into(),
+                    msg: format!(
+                        "a value needs {} {} blocks but {} was generated for {}; \
+                         regenerate it with `zk4log keygen --blocks {}`",
+                        hash.blocks_for(max_preimage_len),
+                        hash.name(),
+                        params_path,
+                        blocks,
+                        hash.blocks_for(max_preimage_len)
+                    ),
+                    span: Some(call.head),
+                });
+            }
+            provers.push((hash, blocks, params));
+        }
+        let vks: Vec<_> = provers
+            .iter()
+            .map(|(hash, _, params)| (*hash, &params.vk))
+            .collect();
+        // バンドルにするときは、鍵もバンドルに入れる
+        if params_path.is_none() && staging.is_none() {
+            keys::write_vk("key.pub", &vks).map_err(|e| write_error("key.pub", e))?;
+        }
 
-        let mut progress_bar = ProgressBar::new(item_count as u64);
-        // ログを秘匿化しつつ ZKP を生成する
-        for (idx, json_data) in json_datas.iter().enumerate() {
-            if let serde_json::Value::Object(ref map) = json_data {
-                let mut new_json_data: Map<String, serde_json::Value> = Map::new();
-                for i in 0..map.len() {
-                    let key = map.keys().nth(i).unwrap();
-                    let value = map.get(key).unwrap();
-
-                    // map_keysにおけるインデックスを求める
-                    let index = map_keys_hashmap.get(key).unwrap();
-
-                    if selections.contains(&index) {
-                        progress_bar.progress();
-                        // ハッシュ化のために、入力ログデータを
-                        // (Stringではなく) 固定長のu8 配列で表現する
-                        let preimage_str = value.clone().to_string() + &salt;
-                        let mut preimage_bytes: [u8; 80] = [0; 80];
-                        preimage_bytes[..preimage_str.len()]
-                            .copy_from_slice(preimage_str.as_bytes());
-
-                        // u8 配列であるハッシュ値を、
-                        // ファイル書き出し用に16進数文字列に変換
-                        let hash_bytes = Sha256::digest(&Sha256::digest(&preimage_bytes));
-                        let hash_str = hash_bytes
-                            .iter()
-                            .map(|b| format!("{:02x}", b))
-                            .collect::<Vec<String>>()
-                            .join("");
-
-                        new_json_data.insert(key.clone(), to_value(hash_str).unwrap());
-
-                        let proof = prove(params.clone(), preimage_str);
-                        let mut file = fs::OpenOptions::new()
-                            .create(true)
-                            .append(true)
-                            .open("out.proof")
-                            .unwrap();
-
-                        // The proof output style is:
-                        //   (<json_record_index>::<json_key>::<proof>)+
-                        let data = format!("{}::{}::", idx, key);
-                        file.write_all(data.as_bytes()).unwrap();
-                        proof.write(&mut file).unwrap();
-                        file.write_all("::".as_bytes()).unwrap();
-
-                        let file = fs::File::create("key.pub").unwrap();
-                        let vk: VerifyingKey<Bls12> = params.vk.clone();
-                        vk.write(file).unwrap();
+        // hide_record: レコードの秘匿化するセルをハッシュ値に置き換え、
+        // 秘匿化したセルの (カラム, ハッシュ, ソルト, ハッシュ化する文字列, ハッシュ値) を返す
+        let hide_record = |idx: usize, json_data: serde_json::Value| {
+            // 秘匿化した値は元のレコードの同じ位置に書き戻す
+            let mut new_json_data = json_data.clone();
+            let mut hidden_cells: Vec<(String, zk::Hash, String, String, String)> = Vec::new();
+            for (key, value) in path::leaves(&json_data) {
+                if let Some(hash) = hash_for(&key, value) {
+                    let salt = if salt_mode == "cell" {
+                        Self::cell_salt(&salt_seed, idx, &key)
                     } else {
-                        new_json_data.insert(key.clone(), value.clone());
-                    }
+                        log_salt.clone()
+                    };
+                    // 入力ログデータは長さに関わらずそのまま回路に渡す
+                    // (回路が複数ブロックのハッシュを扱う)
+                    let preimage_str = Self::preimage(value, &salt);
+
+                    // u8 配列であるハッシュ値を、
+                    // ファイル書き出し用に16進数文字列に変換
+                    let hash_str = hex::encode(hash.digest(preimage_str.as_bytes()));
+
+                    let cell =
+                        path::get_mut(&mut new_json_data, &key).ok_or_else(|| LabeledError {
+                            label: "Cell not found".into(),
+                            msg: format!("record {} has no column {}", idx, key),
+                            span: Some(call.head),
+                        })?;
+                    *cell = serde_json::Value::String(hash_str.clone());
+                    hidden_cells.push((key, hash, salt, preimage_str, hash_str));
                 }
-                new_json_datas.push(serde_json::Value::Object(new_json_data));
+            }
+            Ok::<_, LabeledError>((new_json_data, hidden_cells))
+        };
+
+        let mut output_writer = match &output_name {
+            Some(output_name) => Some(
+                RecordWriter::create(output_name, &output_format)
+                    .map_err(|e| write_error(output_name, e))?,
+            ),
+            None => None,
+        };
+        let mut hidden_table: Vec<serde_json::Value> = Vec::new();
+        // ソルトを保存しておかないと、秘匿化した値を後から開示できない
+        let mut vault = match &vault_key {
+            Some(vault_key) => Some(
+                VaultWriter::create(&vault_name, vault_key, &log_id, default_hash.name())
+                    .map_err(|e| write_error(&vault_name, e))?,
+            ),
+            None => None,
+        };
+
+        // ログ全体のコミットメント (見えているカラムも含めたレコード全体の
+        // コミットメントと、秘匿化したセルの一覧のハッシュチェーン)
+        let mut chain = Chain::new(&log_id);
+        // 3周目: ログを秘匿化して1レコードずつ書き出し、ログ全体のコミットメントを求める
+        for (idx, json_data) in log.records(call)?.enumerate() {
+            let (new_json_data, hidden_cells) = hide_record(idx, json_data?)?;
+            if let Some(vault) = vault.as_mut() {
+                for (key, hash, salt, _, _) in hidden_cells.iter() {
+                    vault
+                        .push(idx, key, salt, hash.name())
+                        .map_err(|e| write_error(&vault_name, e))?;
+                }
+            }
+            let hidden: Vec<String> = hidden_cells.iter().map(|c| c.0.clone()).collect();
+            chain.push(&commitment::record(&new_json_data), &hidden);
+            if let Log::Table(_) = log {
+                hidden_table.push(new_json_data.clone());
+            }
+            if let (Some(output_writer), Some(output_name)) = (output_writer.as_mut(), &output_name)
+            {
+                output_writer
+                    .write(new_json_data)
+                    .map_err(|e| write_error(output_name, e))?;
             }
         }
-        progress_bar.progress();
+        if let (Some(output_writer), Some(output_name)) = (output_writer, &output_name) {
+            output_writer
+                .finish()
+                .map_err(|e| write_error(output_name, e))?;
+        }
 
-        // 要素が1つの場合にも配列にならないようにする
-        let output_data = {
-            if new_json_datas.len() == 1 {
-                serde_json::to_string_pretty(&new_json_datas[0])
-                    .expect("Failed to serialize to JSON")
-            } else {
-                serde_json::to_string_pretty(&new_json_datas).expect("Failed to serialize to JSON")
+        // 検証する人がどのポリシーで秘匿化されたかを確認できるように記録する
+        // (ハッシュごとに、proof を作った回路も記録する)
+        let circuits = provers
+            .iter()
+            .map(|(hash, blocks, _)| (hash.name().to_string(), hash.circuit_id(*blocks)))
+            .collect();
+        let mut header = Header::new(circuits, log_id.clone());
+        header.log = hex::encode(chain.head());
+        header.records = chain.count();
+        header.policy = policy.as_ref().map(|policy| policy.hash.clone());
+        header.columns = hidden_columns;
+        // バンドルのマニフェストには、検証に必要な情報とログを秘匿化した日時を記録する
+        let mut manifest = Manifest::new(
+            header.circuits.clone(),
+            log_id.clone(),
+            header.columns.clone(),
+            &output_format,
+        );
+        if let Log::File { path, .. } = &log {
+            manifest.log_modified = fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339());
+        }
+        let mut proof_writer =
+            ProofWriter::create(&proof_name, header).map_err(|e| write_error(&proof_name, e))?;
+
+        let mut progress_bar = ProgressBar::new(item_count as u64);
+        // 4周目: 各レコードのコミットメントと秘匿化したセルの一覧を記録し、
+        // 秘匿化したセルの ZKP を生成する (proof はログ全体のコミットメントに結び付けるので、
+        // どのレコードを書き換えたり削除したりしても proof が通らなくなる)
+        for (idx, json_data) in log.records(call)?.enumerate() {
+            let (new_json_data, hidden_cells) = hide_record(idx, json_data?)?;
+            proof_writer
+                .write_record(RecordCommitment {
+                    record: idx,
+                    commitment: hex::encode(commitment::record(&new_json_data)),
+                    hidden: hidden_cells.iter().map(|c| c.0.clone()).collect(),
+                })
+                .map_err(|e| write_error(&proof_name, e))?;
+            for (key, hash, _, preimage_str, hash_str) in hidden_cells {
+                progress_bar.progress();
+                let binding = Binding {
+                    log_id: log_id.clone(),
+                    record: idx as u64,
+                    column: key.clone(),
+                    commitment: chain.head(),
+                };
+                // セルを秘匿化したハッシュの回路で proof を作る
+                let (_, blocks, params) = provers
+                    .iter()
+                    .find(|(h, _, _)| *h == hash)
+                    .expect("params are made for every hash of the hidden cells");
+                let proof = prove(params, hash, *blocks, preimage_str.as_bytes(), &binding);
+                let mut proof_bytes = Vec::new();
+                proof.write(&mut proof_bytes).unwrap();
+                proof_writer
+                    .write(Entry {
+                        record: idx,
+                        column: key,
+                        hash_mode: hash.name().to_string(),
+                        hash: Some(hash_str),
+                        proof: proof_bytes,
+                    })
+                    .map_err(|e| write_error(&proof_name, e))?;
             }
-        };
-        let output_name = {
-            if let Some(output) = output {
-                output
-            } else {
-                "output.json".to_string()
+        }
+        progress_bar.progress();
+        proof_writer
+            .finish()
+            .map_err(|e| write_error(&proof_name, e))?;
+        if let (Some(staging), Some(bundle_name)) = (&staging, &bundle_name) {
+            let key_name = staging.path(bundle::KEY);
+            keys::write_vk(&key_name, &vks).map_err(|e| write_error(&key_name, e))?;
+            bundle::write(bundle_name, &manifest, staging, sign_key.as_ref())
+                .map_err(|e| write_error(bundle_name, e))?;
+            eprintln!("\nwrote bundle {}", bundle_name);
+            if let Some(sign_key) = &sign_key {
+                eprintln!(
+                    "signed with key {}",
+                    signing::fingerprint(&sign_key.verifying_key())
+                );
             }
-        };
-        fs::write(output_name, output_data).expect("Unable to write to file");
+        }
+
+        if let Some(vault) = vault {
+            vault.finish().map_err(|e| write_error(&vault_name, e))?;
+            eprintln!("\nwrote salt vault {}", vault_name);
+        } else {
+            eprintln!(
+                "\n{}",
+                "no --vault-recipient or --vault-passphrase given, salts are discarded".yellow()
+            );
+        }
         eprintln!(
             "\n{}",
             "finished making concealed log file and proof!".green()
         );
 
-        Ok(Value::nothing(call.head))
+        match log {
+            Log::File { .. } => Ok(Value::nothing(call.head)),
+            Log::Table(_) => Ok(Value::list(
+                hidden_table
+                    .iter()
+                    .map(|record| Self::nu_value(record, call.head))
+                    .collect(),
+                call.head,
+            )),
+        }
     }
 
     pub fn open(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
@@ -203,152 +547,974 @@ impl Zk4log {
         // pathのファイルが存在するかどうかを確認する
         let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
 
-        let data = fs::read_to_string(&path).expect("Unable to read file");
-        let json_value: serde_json::Value =
-            serde_json::from_str(&data).expect("Invalid JSON format");
-        let mut json_datas: Vec<serde_json::Value> = Vec::new();
+        // ネストされた値は user.ip のようなパスをカラム名とする
+        let format = Self::log_format(call, &path)?;
+        let mut map_keys: Vec<String> = Vec::new();
+        for json_data in Self::records(&path, &format, call)? {
+            for (key, _) in path::leaves(&json_data?) {
+                if !map_keys.contains(&key) {
+                    map_keys.push(key);
+                }
+            }
+        }
+
+        let selections = Self::select_columns(
+            call,
+            &map_keys,
+            &vec![Vec::new(); map_keys.len()],
+            "Select the columns you want to open",
+        )?;
+        let columns: Vec<String> = selections
+            .into_iter()
+            .map(|selection| map_keys[selection].clone())
+            .collect();
 
-        match json_value {
-            serde_json::Value::Array(array_val) => {
-                for item in array_val.iter() {
-                    if let Some(obj) = item.as_object() {
-                        json_datas.push(serde_json::Value::Object(obj.clone()));
-                    } else {
-                        return Err(LabeledError {
-                            label: "Invalid JSON format".into(),
-                            msg: "Invalid JSON format".into(),
-                            span: Some(call.head),
-                        });
+        // ログをもう一度1レコードずつ読み、選んだカラムだけのテーブルを返す
+        // (値のないセルは nothing になる)
+        let rows = Self::records(&path, &format, call)?
+            .map(|json_data| {
+                let json_data = json_data?;
+                let values = columns
+                    .iter()
+                    .map(|column| match path::get(&json_data, column) {
+                        Some(value) => Self::nu_value(value, call.head),
+                        None => Value::nothing(call.head),
+                    })
+                    .collect();
+                Ok(Value::record(columns.clone(), values, call.head))
+            })
+            .collect::<Result<_, LabeledError>>()?;
+
+        Ok(Value::list(rows, call.head))
+    }
+
+    pub fn verify(&self, call: &EvaluatedCall, input: &Value) -> Result<Value, LabeledError> {
+        // log, proof, key があるかを確認 (--json がなければパイプラインのテーブルを検証する)
+        // バンドルが指定されていれば、バンドルに入っているものを使う
+        let bundle_path: Option<String> = call.opt(0)?;
+        let json: Option<String> = call.get_flag("json")?;
+        let proof: Option<String> = call.get_flag("proof")?;
+        let key: Option<String> = call.get_flag("key")?;
+        let missing = |flag: &str| LabeledError {
+            label: format!("Missing --{}", flag),
+            msg: format!("give a bundle or --{}", flag),
+            span: Some(call.head),
+        };
+        // --trusted-signers のディレクトリにある公開鍵の持ち主だけを署名者として信頼する
+        let trusted_signers = match call.get_flag::<String>("trusted-signers")? {
+            Some(_) if bundle_path.is_none() => {
+                return Err(LabeledError {
+                    label: "Missing bundle".into(),
+                    msg: "only bundles are signed, give a bundle to check its signer".into(),
+                    span: Some(call.head),
+                })
+            }
+            Some(dir) => {
+                let dir = Self::expand_tilde_and_check_file_exists(&dir, call)?;
+                Some(signing::trusted_signers(&dir).map_err(|e| LabeledError {
+                    label: "Invalid trusted signers".into(),
+                    msg: format!("{}: {}", dir, e),
+                    span: Some(call.head),
+                })?)
+            }
+            None => None,
+        };
+        // 鍵はハッシュごとに異なるので、カンマ区切りで複数のファイルを指定できる
+        let key_paths = |keys: &str| {
+            keys.split(',')
+                .map(|key| Self::expand_tilde_and_check_file_exists(key.trim(), call))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let (log, proof, key_paths, bundle) = match bundle_path {
+            Some(_) if json.is_some() || proof.is_some() => {
+                return Err(LabeledError {
+                    label: "Conflicting input".into(),
+                    msg: "a bundle cannot be combined with --json or --proof".into(),
+                    span: Some(call.head),
+                })
+            }
+            Some(bundle_path) => {
+                let bundle_path = Self::expand_tilde_and_check_file_exists(&bundle_path, call)?;
+                let invalid_bundle = |msg: String| LabeledError {
+                    label: "Invalid bundle".into(),
+                    msg: format!("{}: {}", bundle_path, msg),
+                    span: Some(call.head),
+                };
+                let Bundle {
+                    dir: staging,
+                    manifest,
+                    signer,
+                } = bundle::unpack(&bundle_path).map_err(|e| invalid_bundle(e.to_string()))?;
+                let format = manifest.log_format().ok_or_else(|| {
+                    invalid_bundle(format!("unknown log format {}", manifest.log_format))
+                })?;
+                eprintln!(
+                    "bundle made by {} at {} (log id {})",
+                    manifest.tool, manifest.created, manifest.log_id
+                );
+                // 信頼できる鍵が --key で与えられていなければ、バンドルの鍵を使う
+                let key_paths = match key {
+                    Some(key) => key_paths(&key)?,
+                    None => {
+                        eprintln!(
+                            "{}",
+                            "using the verifying key in the bundle, give --key to use a trusted one"
+                                .yellow()
+                        );
+                        vec![staging.path(bundle::KEY)]
                     }
-                }
+                };
+                let log = Log::File {
+                    path: staging.path(&manifest.log),
+                    format,
+                };
+                let proof = staging.path(bundle::PROOF);
+                (log, proof, key_paths, Some((staging, manifest, signer)))
             }
-            serde_json::Value::Object(obj) => {
-                json_datas.push(serde_json::Value::Object(obj));
+            None => {
+                let proof = proof.ok_or_else(|| missing("proof"))?;
+                let proof = Self::expand_tilde_and_check_file_exists(&proof, call)?;
+                let key_paths = key_paths(&key.ok_or_else(|| missing("key"))?)?;
+                (Log::new(json, call, input)?, proof, key_paths, None)
             }
-            _ => {
+        };
+        let report_json = match call.get_flag::<String>("report-format")?.as_deref() {
+            None | Some("table") => false,
+            Some("json") => true,
+            Some(_) => {
+                return Err(LabeledError {
+                    label: "Invalid report format".into(),
+                    msg: "--report-format must be \"table\" or \"json\"".into(),
+                    span: Some(call.head),
+                })
+            }
+        };
+
+        // proof ファイルはヘッダだけを読み、proof はログと並べて1行ずつ読む
+        let invalid_proof = |e: io::Error| LabeledError {
+            label: "Invalid proof file".into(),
+            msg: format!("{}: {}", proof, e),
+            span: Some(call.head),
+        };
+        let items = proof_file::Reader::open(&proof).map_err(invalid_proof)?;
+        let header = items.header.clone();
+        // マニフェストと proof ファイルのヘッダが食い違うバンドルは受け付けない
+        if let Some((_, manifest, _)) = &bundle {
+            if (&manifest.circuits, &manifest.log_id, &manifest.columns)
+                != (&header.circuits, &header.log_id, &header.columns)
+            {
                 return Err(LabeledError {
-                    label: "Invalid JSON format".into(),
-                    msg: "Invalid JSON format".into(),
+                    label: "Invalid bundle".into(),
+                    msg: "the manifest does not match the proof file".into(),
                     span: Some(call.head),
                 });
             }
         }
+        // 回路はハッシュごとに異なるので、セルを秘匿化したときと同じハッシュで検証する
+        let mut hashes = Vec::new();
+        for name in header.circuits.keys() {
+            hashes.push(zk::Hash::parse(name).ok_or_else(|| LabeledError {
+                label: "Unsupported hash".into(),
+                msg: format!("{} was made with unsupported hash {}", proof, name),
+                span: Some(call.head),
+            })?);
+        }
 
-        let mut map_keys: Vec<String> = Vec::new();
-        for json_data in json_datas.iter() {
-            if let serde_json::Value::Object(ref map) = json_data {
-                for key in map.keys() {
-                    if !map_keys.contains(key) {
-                        map_keys.push(key.clone());
+        // --policy があれば、そのポリシーで秘匿化されたかを確認する
+        if let Some(policy) = Self::read_policy(call)? {
+            if header.policy.as_ref() != Some(&policy.hash) {
+                return Err(LabeledError {
+                    label: "Policy mismatch".into(),
+                    msg: format!(
+                        "{} was not hidden with the given policy (policy hash {}, proof file {})",
+                        proof,
+                        policy.hash,
+                        header.policy.as_deref().unwrap_or("none")
+                    ),
+                    span: Some(call.head),
+                });
+            }
+        } else if let Some(policy) = &header.policy {
+            eprintln!("hidden with policy {}", policy);
+        }
+
+        // key をロード (ハッシュを記録していない古い鍵は、1つのハッシュで
+        // 秘匿化したログにだけ使える)
+        let mut pvks: Vec<(zk::Hash, PreparedVerifyingKey<Bls12>)> = Vec::new();
+        for key in key_paths.iter() {
+            let invalid_key = |msg: String| LabeledError {
+                label: "Invalid key file".into(),
+                msg: format!("failed to load {}: {}", key, msg),
+                span: Some(call.head),
+            };
+            for (hash, vk) in keys::read_vk(key).map_err(|e| invalid_key(e.to_string()))? {
+                let hash =
+                    match (hash, &hashes[..]) {
+                        (Some(hash), _) => hash,
+                        (None, [hash]) => *hash,
+                        (None, _) => return Err(invalid_key(
+                            "the key does not name its hash, make it again with `zk4log keygen`"
+                                .to_string(),
+                        )),
+                    };
+                pvks.push((hash, groth16::prepare_verifying_key(&vk)));
+            }
+        }
+        if let Some(hash) = hashes
+            .iter()
+            .find(|&&hash| pvks.iter().all(|(h, _)| *h != hash))
+        {
+            return Err(LabeledError {
+                label: "Missing key".into(),
+                msg: format!(
+                    "{} has proofs made with {} but no key for it was given",
+                    proof,
+                    hash.name()
+                ),
+                span: Some(call.head),
+            });
+        }
+
+        // 秘匿化されたログは1レコードずつ読み、レコード順に書かれた proof ファイルと
+        // 突き合わせる (どちらもメモリに載せるのは1レコード分だけ)
+        let mut items = items.peekable();
+        // proof はすべて、hide したときのログ全体のコミットメントに結び付いている
+        let log_commitment: [u8; commitment::BYTES] = hex::decode(&header.log)
+            .ok()
+            .and_then(|log| log.try_into().ok())
+            .ok_or_else(|| LabeledError {
+                label: "Invalid proof file".into(),
+                msg: format!("{}: invalid log commitment {}", proof, header.log),
+                span: Some(call.head),
+            })?;
+        let mut log_chain = Chain::new(&header.log_id);
+
+        // セルごとに検証結果を記録する
+        // レコード単位の行は、検査に失敗したときだけ column を空にして報告する
+        let mut report: Vec<serde_json::Value> = Vec::new();
+        let mut push_row =
+            |record: Option<usize>, column: Option<&str>, status: &str, hash: Option<&str>| {
+                report.push(serde_json::json!({
+                    "record": record,
+                    "column": column,
+                    "status": status,
+                    "hash": hash,
+                }))
+            };
+        for (idx, record) in log.records(call)?.enumerate() {
+            let record = record?;
+
+            // レコード全体のコミットメントを計算し直し、記録されたものと比べる
+            // (見えているカラムの書き換え、キーの並べ替え、カラムの追加・削除を検出する)
+            let commitment = commitment::record(&record);
+            // proof ファイルからこのレコードの行を読む
+            // (それより前のレコードの行が残っていれば、順番が入れ替わっている)
+            let mut stored_commitment = None;
+            let mut entries = Vec::new();
+            while let Some(item) =
+                items.next_if(|item| item.as_ref().map_or(true, |item| item.record() <= idx))
+            {
+                match item.map_err(invalid_proof)? {
+                    Item::Record(c) if c.record < idx => {
+                        push_row(Some(c.record), None, "out of order", Some(&c.commitment))
+                    }
+                    Item::Proof(entry) if entry.record < idx => push_row(
+                        Some(entry.record),
+                        Some(&entry.column),
+                        "out of order",
+                        None,
+                    ),
+                    Item::Record(c) => stored_commitment = Some(c),
+                    Item::Proof(entry) => entries.push(entry),
+                }
+            }
+            // 秘匿化したセルの一覧もログ全体のコミットメントに含まれている
+            match &stored_commitment {
+                Some(stored) if stored.commitment == hex::encode(commitment) => (),
+                Some(stored) => {
+                    push_row(Some(idx), None, "record changed", Some(&stored.commitment))
+                }
+                None => push_row(Some(idx), None, "unproven record", None),
+            }
+            let hidden = stored_commitment.map(|stored| stored.hidden);
+            log_chain.push(&commitment, hidden.as_deref().unwrap_or_default());
+
+            let mut proven: Vec<String> = Vec::new();
+            for entry in entries {
+                let cell = path::get(&record, &entry.column);
+                let stored_hash = cell.and_then(|hash| hash.as_str());
+                let status = match (
+                    cell,
+                    Proof::<Bls12>::read(&entry.proof[..]),
+                    stored_hash.and_then(|hash| hex::decode(hash).ok()),
+                ) {
+                    _ if proven.contains(&entry.column) => "duplicate proof",
+                    // proof が指すセルがログにないか、秘匿化したセルとして記録されていない
+                    (None, _, _) => "orphan proof",
+                    _ if hidden.as_ref().is_some_and(|h| !h.contains(&entry.column)) => {
+                        "orphan proof"
                     }
+                    (_, Err(_), _) => "malformed proof",
+                    (_, _, None) => "missing cell",
+                    // proof ファイルに記録したハッシュ値と違えば、ログのセルが書き換えられている
+                    _ if entry.hash.is_some() && entry.hash.as_deref() != stored_hash => {
+                        "hash mismatch"
+                    }
+                    (_, Ok(proof), Some(hash)) => {
+                        let binding = Binding {
+                            log_id: header.log_id.clone(),
+                            record: idx as u64,
+                            column: entry.column.clone(),
+                            commitment: log_commitment,
+                        };
+                        // セルを秘匿化したハッシュの鍵で検証する
+                        let pvk = pvks
+                            .iter()
+                            .find(|(h, _)| h.name() == entry.hash_mode)
+                            .map(|(_, pvk)| pvk);
+                        match pvk {
+                            Some(pvk) if zk::verify(pvk, &hash, &binding, &proof) => "ok",
+                            _ => "bad proof",
+                        }
+                    }
+                };
+                push_row(Some(idx), Some(&entry.column), status, stored_hash);
+                proven.push(entry.column);
+            }
+
+            // 秘匿化したと記録されたセルには proof がなければならない
+            for column in hidden.iter().flatten() {
+                if !proven.contains(column) {
+                    let value = path::get(&record, column).and_then(|value| value.as_str());
+                    push_row(Some(idx), Some(column), "unproven cell", value);
+                }
+            }
+        }
+        // ログのレコード数を超える proof とコミットメント
+        for item in items {
+            match item.map_err(invalid_proof)? {
+                Item::Record(c) => {
+                    push_row(Some(c.record), None, "out of range", Some(&c.commitment))
                 }
+                Item::Proof(entry) => push_row(
+                    Some(entry.record),
+                    Some(&entry.column),
+                    "out of range",
+                    None,
+                ),
             }
         }
 
-        let selections = MultiSelect::with_theme(&ColorfulTheme::default())
-            .with_prompt("Select the columns you want to open")
-            .items(&map_keys)
-            .interact()
-            .unwrap();
+        // ログ全体: レコード数と、全レコードのコミットメントのハッシュチェーンを比べる
+        // (レコードの削除、挿入、並べ替え、末尾の切り捨て、見えているカラムの書き換えを
+        // 検出する。proof ファイルの記録ごと書き換えても、proof が通らなくなる)
+        if log_chain.count() < header.records {
+            push_row(None, None, "records missing", None);
+        } else if log_chain.count() > header.records {
+            push_row(None, None, "records added", None);
+        }
+        if log_chain.head() != log_commitment {
+            push_row(None, None, "log changed", Some(&header.log));
+        }
 
-        let mut open_json_columns: Vec<String> = Vec::new();
-        for selection in selections {
-            open_json_columns.push(map_keys.get(selection).unwrap().clone());
+        // 署名: バンドルに署名した鍵が信頼できる署名者のものかを確かめ、署名者を報告する
+        if let Some((_, _, signer)) = &bundle {
+            let signature = match (signer, &trusted_signers) {
+                (None, None) => None,
+                (None, Some(_)) => Some(("unsigned", None)),
+                (Some(Err(e)), _) => {
+                    eprintln!("{}", format!("invalid signature: {}", e).red());
+                    Some(("bad signature", None))
+                }
+                (Some(Ok(key)), None) => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "signed with key {}, give --trusted-signers to check the signer",
+                            signing::fingerprint(key)
+                        )
+                        .yellow()
+                    );
+                    None
+                }
+                (Some(Ok(key)), Some(trusted)) => {
+                    match trusted.iter().find(|(_, trusted)| trusted == key) {
+                        Some((name, _)) => {
+                            eprintln!("signed by {}", name);
+                            Some(("ok", Some(name.clone())))
+                        }
+                        None => Some(("untrusted signer", Some(signing::fingerprint(key)))),
+                    }
+                }
+            };
+            if let Some((status, signer)) = signature {
+                report.push(serde_json::json!({
+                    "record": None::<usize>,
+                    "column": None::<String>,
+                    "status": status,
+                    "hash": None::<String>,
+                    "signer": signer,
+                }));
+            }
         }
 
-        let open_json_columns = open_json_columns.join(" ");
-        let command_str = format!("open {} | select -i {}", &path, open_json_columns);
-        eprintln!("{}", command_str);
-        let output = Command::new("nu")
-            .arg("-c")
-            .arg(command_str)
-            .output()
-            .expect("Failed to execute command");
-        eprintln!("{}", String::from_utf8_lossy(&output.stdout));
+        let failed = report.iter().filter(|row| row["status"] != "ok").count();
+        if failed == 0 {
+            eprintln!("Verify OK!");
+        } else {
+            eprintln!(
+                "{}",
+                format!("{} of {} checks failed", failed, report.len()).red()
+            );
+        }
 
-        return Ok(Value::nothing(call.head));
+        // --report-format json ならチケットシステムなどに渡せる JSON 文字列を返す
+        if report_json {
+            Ok(Value::string(
+                serde_json::to_string_pretty(&report).unwrap(),
+                call.head,
+            ))
+        } else {
+            Ok(Value::list(
+                report
+                    .iter()
+                    .map(|row| Self::nu_value(row, call.head))
+                    .collect(),
+                call.head,
+            ))
+        }
     }
 
-    pub fn verify(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
-        // log, proof, key があるかを確認
-        let log: String = call.get_flag_value("json").unwrap().as_string().unwrap();
-        let log = Self::expand_tilde_and_check_file_exists(&log, call)?;
-        let proof: String = call.get_flag_value("proof").unwrap().as_string().unwrap();
-        let proof = Self::expand_tilde_and_check_file_exists(&proof, call)?;
-        let key: String = call.get_flag_value("key").unwrap().as_string().unwrap();
-        let key = Self::expand_tilde_and_check_file_exists(&key, call)?;
+    pub fn keygen(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
+        if call.has_flag("signing") {
+            return Self::keygen_signing(call);
+        }
+        let hash = Self::hash_flag(call)?.unwrap_or_default();
+        let blocks: i64 = call.get_flag("blocks")?.unwrap_or(DEFAULT_BLOCKS);
+        let params_path: String = call
+            .get_flag("params")?
+            .unwrap_or_else(|| "zk4log.params".to_string());
+        let key_path: String = call
+            .get_flag("key")?
+            .unwrap_or_else(|| "key.pub".to_string());
+
+        if blocks < 1 {
+            return Err(LabeledError {
+                label: "Invalid number of blocks".into(),
+                msg: "--blocks must be at least 1".into(),
+                span: Some(call.head),
+            });
+        }
+        let blocks = blocks as usize;
 
-        // log をロード
-        let log = fs::read_to_string(&log).unwrap();
-        let log_json: serde_json::Value = from_str(&log).unwrap();
+        eprint!("making keys...");
+        let (params, _pvk) = zk::setup(hash, blocks);
+        eprintln!("\r{}", "Finished making keys!".green());
+
+        let write_error = |path: &str, e: io::Error| LabeledError {
+            label: "Unable to write file".into(),
+            msg: format!("failed to write {}: {}", path, e),
+            span: Some(call.head),
+        };
+        keys::write_params(&params_path, hash, blocks, &params)
+            .map_err(|e| write_error(&params_path, e))?;
+        keys::write_vk(&key_path, &[(hash, &params.vk)]).map_err(|e| write_error(&key_path, e))?;
+        eprintln!(
+            "wrote {} and {} ({} values up to {} bytes including salt)",
+            params_path,
+            key_path,
+            hash.name(),
+            hash.capacity(blocks)
+        );
 
-        // proof をロード
-        let mut proof_buf = Vec::new();
-        let mut file_reader = io::BufReader::new(fs::File::open(&proof).unwrap());
-        file_reader.read_to_end(&mut proof_buf).unwrap();
+        Ok(Value::nothing(call.head))
+    }
+
+    // keygen_signing: バンドルに署名するための Ed25519 の鍵ペアを作る
+    // (秘密鍵は公開鍵と同じ名前で拡張子を .key にしたファイルに書き出す)
+    fn keygen_signing(call: &EvaluatedCall) -> Result<Value, LabeledError> {
+        if call.has_flag("blocks") || call.has_flag("params") || call.has_flag("hash") {
+            return Err(LabeledError {
+                label: "Conflicting options".into(),
+                msg: "--signing makes an Ed25519 key pair, \
+                      --blocks, --params and --hash are not used"
+                    .into(),
+                span: Some(call.head),
+            });
+        }
+        let key_path: String = call
+            .get_flag("key")?
+            .unwrap_or_else(|| "signing.pub".to_string());
+        let secret_path = std::path::Path::new(&key_path)
+            .with_extension("key")
+            .to_string_lossy()
+            .into_owned();
+        if secret_path == key_path {
+            return Err(LabeledError {
+                label: "Invalid key file name".into(),
+                msg: "the public key must not end in .key".into(),
+                span: Some(call.head),
+            });
+        }
 
-        // key をロード
-        let pvk: PreparedVerifyingKey<Bls12> = groth16::prepare_verifying_key(
-            &VerifyingKey::read(io::BufReader::new(fs::File::open(&key).unwrap())).unwrap(),
+        let write_error = |path: &str, e: io::Error| LabeledError {
+            label: "Unable to write file".into(),
+            msg: format!("failed to write {}: {}", path, e),
+            span: Some(call.head),
+        };
+        let key = signing::generate();
+        signing::write_signing_key(&secret_path, &key).map_err(|e| write_error(&secret_path, e))?;
+        signing::write_public_key(&key_path, &key.verifying_key())
+            .map_err(|e| write_error(&key_path, e))?;
+        eprintln!(
+            "wrote {} and {} (key {}); keep {} secret and give {} to verifiers",
+            secret_path,
+            key_path,
+            signing::fingerprint(&key.verifying_key()),
+            secret_path,
+            key_path
         );
 
-        // proof は `<json_record_no>::<json_key>::<proof_data>` という形式。
-        // proof のパースには `split("::")` ではなく ascii 版の[58, 58] を使う。
-        // <proof_data> が String ではないことにより、u8 配列として proof を
-        // 扱うためである。
+        Ok(Value::nothing(call.head))
+    }
+
+    pub fn reveal(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
+        let path: String = call.req(0)?;
+        let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
+        // 保管庫のデフォルトは hide と同じく、秘匿化したログの名前に .vault を付けたもの
+        let format = Self::log_format(call, &path)?;
+        let vault_path: String = call
+            .get_flag("vault")?
+            .unwrap_or_else(|| format!("output.{}.vault", format.extension()));
+        let vault_path = Self::expand_tilde_and_check_file_exists(&vault_path, call)?;
+        let output: String = call
+            .get_flag("output")?
+            .unwrap_or_else(|| "opening.json".to_string());
+
+        // --records 3,7 --columns user,ip のようにカンマ区切りで指定する
+        let records = match call.get_flag::<String>("records")? {
+            Some(records) => Some(
+                records
+                    .split(',')
+                    .map(|r| r.trim().parse::<usize>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| LabeledError {
+                        label: "Invalid record list".into(),
+                        msg: format!("--records {}: {}", records, e),
+                        span: Some(call.head),
+                    })?,
+            ),
+            None => None,
+        };
+        let columns = call
+            .get_flag::<String>("columns")?
+            .map(|columns| columns::parse_patterns(&columns))
+            .transpose()
+            .map_err(|e| LabeledError {
+                label: "Invalid column selection".into(),
+                msg: e,
+                span: Some(call.head),
+            })?;
+
+        let key = match call.get_flag::<String>("identity")? {
+            Some(identity) => {
+                let identity = Self::expand_tilde_and_check_file_exists(&identity, call)?;
+                VaultKey::read_identity(&identity).map_err(|e| LabeledError {
+                    label: "Invalid identity file".into(),
+                    msg: format!("{}: {}", identity, e),
+                    span: Some(call.head),
+                })?
+            }
+            None => VaultKey::Passphrase(Self::vault_passphrase(call, false)?),
+        };
+        let vault = Vault::read(&vault_path, &key).map_err(|e| LabeledError {
+            label: "Unable to open salt vault".into(),
+            msg: format!("{}: {}", vault_path, e),
+            span: Some(call.head),
+        })?;
 
-        let delimiter: [u8; 2] = [58, 58]; // "::" のバイト表現
+        let json_datas = Self::read_json_records(&path, call)?;
 
-        let mut start = 0;
-        let mut buf = Vec::new();
-        for (i, byte) in proof_buf.iter().enumerate() {
-            if byte == &delimiter[0] && i + 1 < proof_buf.len() && proof_buf[i + 1] == delimiter[1]
+        let mut opening = Opening::new(&vault.hash, vault.log_id.clone(), format.extension());
+        for entry in vault.salts.iter() {
+            if records.as_ref().is_some_and(|r| !r.contains(&entry.record))
+                || columns
+                    .as_ref()
+                    .is_some_and(|c| !columns::matches_any(c, &entry.column))
             {
-                // delimiter を見つけた場合、区切り位置までの部分を処理
-                let chunk = &proof_buf[start..i];
+                continue;
+            }
+
+            let value = json_datas
+                .get(entry.record)
+                .and_then(|record| path::get(record, &entry.column))
+                .ok_or_else(|| LabeledError {
+                    label: "Cell not found".into(),
+                    msg: format!(
+                        "record {} of {} has no column {}",
+                        entry.record, path, entry.column
+                    ),
+                    span: Some(call.head),
+                })?;
+            opening.cells.push(OpenedCell {
+                record: entry.record,
+                column: entry.column.clone(),
+                value: value.clone(),
+                salt: entry.salt.clone(),
+                hash: entry.hash.clone(),
+            });
+        }
+
+        if opening.cells.is_empty() {
+            return Err(LabeledError {
+                label: "Nothing to reveal".into(),
+                msg: "no hidden cell in the vault matches --records and --columns".into(),
+                span: Some(call.head),
+            });
+        }
+
+        opening.write(&output).map_err(|e| LabeledError {
+            label: "Unable to write file".into(),
+            msg: format!("failed to write {}: {}", output, e),
+            span: Some(call.head),
+        })?;
+        eprintln!("revealed {} cells in {}", opening.cells.len(), output);
 
-                // バイト配列から文字列への変換
-                buf.push(chunk);
+        Ok(Value::nothing(call.head))
+    }
 
-                // 次の区切り位置の開始位置を更新
-                start = i + 2; // delimiter の長さ分進める
+    pub fn check_reveal(
+        &self,
+        call: &EvaluatedCall,
+        _input: &Value,
+    ) -> Result<Value, LabeledError> {
+        let path: String = call.req(0)?;
+        let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
+        let opening = Opening::read(&path).map_err(|e| LabeledError {
+            label: "Invalid opening file".into(),
+            msg: format!("{}: {}", path, e),
+            span: Some(call.head),
+        })?;
+        // 秘匿化したログと proof ファイルのデフォルトは hide が書き出す名前
+        let log: String = call.get_flag("json")?.unwrap_or_else(|| {
+            format!("output.{}", opening.extension.as_deref().unwrap_or("json"))
+        });
+        let log = Self::expand_tilde_and_check_file_exists(&log, call)?;
+        let proof: String = call
+            .get_flag("proof")?
+            .unwrap_or_else(|| "out.proof".to_string());
+        let proof = Self::expand_tilde_and_check_file_exists(&proof, call)?;
+
+        // 開示されたセルが、別のログのものでないことを proof ファイルのヘッダで確かめる
+        let header = proof_file::Reader::open(&proof)
+            .map_err(|e| LabeledError {
+                label: "Invalid proof file".into(),
+                msg: format!("{}: {}", proof, e),
+                span: Some(call.head),
+            })?
+            .header;
+        // (セルのハッシュも、そのログを秘匿化したハッシュのどれかでなければならない)
+        let mut opened_hashes: Vec<&str> = Vec::new();
+        for cell in opening.cells.iter() {
+            let hash = cell.hash.as_deref().unwrap_or(&opening.hash);
+            if !opened_hashes.contains(&hash) {
+                opened_hashes.push(hash);
             }
         }
+        if header.log_id != opening.log_id
+            || opened_hashes
+                .iter()
+                .any(|hash| !header.circuits.contains_key(*hash))
+        {
+            let hidden_hashes: Vec<&str> = header.circuits.keys().map(String::as_str).collect();
+            return Err(LabeledError {
+                label: "Opening does not match log".into(),
+                msg: format!(
+                    "{} discloses cells of log {} ({}), but {} is for log {} ({})",
+                    path,
+                    opening.log_id,
+                    opened_hashes.join(", "),
+                    proof,
+                    header.log_id,
+                    hidden_hashes.join(", ")
+                ),
+                span: Some(call.head),
+            });
+        }
 
-        // 最後の区切り位置から終端までの部分を処理
-        let chunk = &proof_buf[start..];
-        buf.push(chunk);
+        let json_datas = Self::read_json_records(&log, call)?;
 
-        let mut verify_ok = true;
-        for i in 0..buf.len() / 3 {
-            let idx: usize = std::str::from_utf8(buf[3 * i]).unwrap().parse().unwrap();
-            let k = std::str::from_utf8(buf[3 * i + 1])
-                .unwrap()
-                .trim_matches('"');
-            let proof: Proof<Bls12> = Proof::read(io::Cursor::new(buf[3 * i + 2])).unwrap();
-            let hash = log_json[idx][k].as_str().unwrap().trim_matches('"');
-            let hash = &hex::decode(hash).unwrap();
-            verify_ok &= zk::verify(&pvk, &hash, &proof);
+        // 開示された値とソルトからハッシュ値を計算し直し、秘匿化されたログと比べる
+        let mut check_ok = true;
+        let mut rows = Vec::new();
+        for cell in opening.cells.iter() {
+            let name = cell.hash.as_deref().unwrap_or(&opening.hash);
+            let Some(hash) = zk::Hash::parse(name) else {
+                return Err(LabeledError {
+                    label: "Unsupported hash".into(),
+                    msg: format!("{} was made with unsupported hash {}", path, name),
+                    span: Some(call.head),
+                });
+            };
+            let hash = hex::encode(hash.digest(Self::preimage(&cell.value, &cell.salt).as_bytes()));
+            let status = match json_datas
+                .get(cell.record)
+                .and_then(|record| path::get(record, &cell.column))
+                .and_then(|hidden| hidden.as_str())
+            {
+                Some(hidden) if hidden == hash => "ok",
+                Some(_) => "hash mismatch",
+                None => "missing cell",
+            };
+            check_ok &= status == "ok";
+
+            rows.push(Value::record(
+                vec![
+                    "record".to_string(),
+                    "column".to_string(),
+                    "value".to_string(),
+                    "status".to_string(),
+                ],
+                vec![
+                    Value::int(cell.record as i64, call.head),
+                    Value::string(cell.column.clone(), call.head),
+                    Value::string(cell.value.to_string(), call.head),
+                    Value::string(status, call.head),
+                ],
+                call.head,
+            ));
         }
 
-        if verify_ok {
-            eprintln!("Verify OK!");
-            return Ok(Value::nothing(call.head));
+        if check_ok {
+            eprintln!("{}", "Reveal OK!".green());
         } else {
+            eprintln!(
+                "{}",
+                "Some revealed values do not match the hidden log".red()
+            );
+        }
+        Ok(Value::list(rows, call.head))
+    }
+
+    // select_columns: --columns / --all-except があればそれに従い、
+    // なければ対話的にカラムを選ばせる (pii が見つかったカラムは選択済みにする)
+    fn select_columns(
+        call: &EvaluatedCall,
+        map_keys: &[String],
+        pii: &[Vec<scan::Kind>],
+        prompt: &str,
+    ) -> Result<Vec<usize>, LabeledError> {
+        let columns: Option<String> = call.get_flag("columns")?;
+        let all_except: Option<String> = call.get_flag("all-except")?;
+        let selections = columns::select(map_keys, columns.as_deref(), all_except.as_deref())
+            .map_err(|e| LabeledError {
+                label: "Invalid column selection".into(),
+                msg: e,
+                span: Some(call.head),
+            })?;
+        if let Some(selections) = selections {
+            return Ok(selections);
+        }
+
+        let items: Vec<(String, bool)> = map_keys
+            .iter()
+            .zip(pii)
+            .map(|(key, kinds)| {
+                if kinds.is_empty() {
+                    (key.clone(), false)
+                } else {
+                    let kinds: Vec<&str> = kinds.iter().map(|k| k.name()).collect();
+                    (format!("{} (PII: {})", key, kinds.join(", ")), true)
+                }
+            })
+            .collect();
+        MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .items_checked(&items)
+            .interact()
+            .map_err(|e| LabeledError {
+                label: "No columns selected".into(),
+                msg: format!(
+                    "pass --columns or --all-except when not running interactively: {}",
+                    e
+                ),
+                span: Some(call.head),
+            })
+    }
+
+    pub fn scan(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
+        let path: String = call.req(0)?;
+        let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
+        let sample: i64 = call
+            .get_flag("sample")?
+            .unwrap_or(scan::DEFAULT_SAMPLE as i64);
+        if sample < 1 {
             return Err(LabeledError {
-                label: "Verify fialed".into(),
-                msg: "verify failed".into(),
+                label: "Invalid sample size".into(),
+                msg: "--sample must be at least 1".into(),
                 span: Some(call.head),
             });
         }
+
+        let json_datas = Self::read_json_records(&path, call)?;
+        let rows = scan::scan(&json_datas, sample as usize)
+            .into_iter()
+            .map(|finding| {
+                Value::record(
+                    vec![
+                        "column".to_string(),
+                        "kind".to_string(),
+                        "matches".to_string(),
+                        "sampled".to_string(),
+                    ],
+                    vec![
+                        Value::string(finding.column, call.head),
+                        Value::string(finding.kind.name(), call.head),
+                        Value::int(finding.matches as i64, call.head),
+                        Value::int(finding.sampled as i64, call.head),
+                    ],
+                    call.head,
+                )
+            })
+            .collect();
+
+        Ok(Value::list(rows, call.head))
+    }
+
+    // nu_value: JSON の値を Nushell の値に変換する
+    fn nu_value(value: &serde_json::Value, span: Span) -> Value {
+        match value {
+            serde_json::Value::Null => Value::nothing(span),
+            serde_json::Value::Bool(b) => Value::bool(*b, span),
+            // Nushell の int に収まらない整数 (i64::MAX を超える u64) は、
+            // float にすると値が変わるので JSON と同じ表記の文字列にする
+            serde_json::Value::Number(n) => match (n.as_i64(), n.as_f64()) {
+                (Some(i), _) => Value::int(i, span),
+                (None, Some(f)) if !n.is_u64() => Value::float(f, span),
+                _ => Value::string(n.to_string(), span),
+            },
+            serde_json::Value::String(s) => Value::string(s.clone(), span),
+            serde_json::Value::Array(array) => Value::list(
+                array.iter().map(|v| Self::nu_value(v, span)).collect(),
+                span,
+            ),
+            serde_json::Value::Object(map) => Value::record(
+                map.keys().cloned().collect(),
+                map.values().map(|v| Self::nu_value(v, span)).collect(),
+                span,
+            ),
+        }
+    }
+
+    // json_value: Nushell の値を JSON の値に変換する
+    fn json_value(value: &Value) -> Result<serde_json::Value, String> {
+        Ok(match value {
+            Value::Nothing { .. } => serde_json::Value::Null,
+            Value::Bool { val, .. } => serde_json::Value::from(*val),
+            Value::Int { val, .. } | Value::Filesize { val, .. } | Value::Duration { val, .. } => {
+                serde_json::Value::from(*val)
+            }
+            Value::Float { val, .. } => serde_json::Value::from(*val),
+            Value::String { val, .. } => serde_json::Value::from(val.clone()),
+            Value::Date { val, .. } => serde_json::Value::from(val.to_rfc3339()),
+            Value::List { vals, .. } => serde_json::Value::Array(
+                vals.iter()
+                    .map(Self::json_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Record { cols, vals, .. } => serde_json::Value::Object(
+                cols.iter()
+                    .zip(vals)
+                    .map(|(col, val)| Ok((col.clone(), Self::json_value(val)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            value => return Err(format!("unsupported value of type {}", value.get_type())),
+        })
+    }
+
+    // hash_flag: --hash で選んだハッシュ (指定がなければ None)
+    fn hash_flag(call: &EvaluatedCall) -> Result<Option<zk::Hash>, LabeledError> {
+        let Some(name) = call.get_flag::<String>("hash")? else {
+            return Ok(None);
+        };
+        zk::Hash::parse(&name)
+            .map(Some)
+            .ok_or_else(|| LabeledError {
+                label: "Invalid hash mode".into(),
+                msg: format!("--hash must be {}", zk::Hash::NAMES),
+                span: Some(call.head),
+            })
+    }
+
+    // read_policy: --policy で指定された秘匿化ポリシーを読み込む
+    fn read_policy(call: &EvaluatedCall) -> Result<Option<Policy>, LabeledError> {
+        let Some(path) = call.get_flag::<String>("policy")? else {
+            return Ok(None);
+        };
+        let path = Self::expand_tilde_and_check_file_exists(&path, call)?;
+        Policy::read(&path).map(Some).map_err(|e| LabeledError {
+            label: "Invalid policy file".into(),
+            msg: format!("{}: {}", path, e),
+            span: Some(call.head),
+        })
+    }
+
+    // vault_key: ソルトの保管庫を暗号化する鍵をフラグから決める
+    fn vault_key(call: &EvaluatedCall) -> Result<Option<VaultKey>, LabeledError> {
+        if let Some(recipient) = call.get_flag::<String>("vault-recipient")? {
+            let recipient = recipient.parse().map_err(|e| LabeledError {
+                label: "Invalid vault recipient".into(),
+                msg: format!("{}: {}", recipient, e),
+                span: Some(call.head),
+            })?;
+            return Ok(Some(VaultKey::Recipient(recipient)));
+        }
+        if !call.has_flag("vault-passphrase") {
+            return Ok(None);
+        }
+
+        let passphrase = Self::vault_passphrase(call, true)?;
+        Ok(Some(VaultKey::Passphrase(passphrase)))
+    }
+
+    // vault_passphrase: ソルトの保管庫のパスフレーズを環境変数か入力から得る
+    fn vault_passphrase(call: &EvaluatedCall, confirm: bool) -> Result<String, LabeledError> {
+        if let Ok(passphrase) = std::env::var(VAULT_PASSPHRASE_ENV) {
+            return Ok(passphrase);
+        }
+
+        let theme = ColorfulTheme::default();
+        let mut prompt = Password::with_theme(&theme);
+        prompt.with_prompt("Salt vault passphrase");
+        if confirm {
+            prompt.with_confirmation("Repeat passphrase", "Passphrases do not match");
+        }
+        prompt.interact().map_err(|e| LabeledError {
+            label: "No vault passphrase".into(),
+            msg: format!("set {} or run interactively: {}", VAULT_PASSPHRASE_ENV, e),
+            span: Some(call.head),
+        })
+    }
+
+    // preimage: セルの値とソルトから、ハッシュ化する文字列を作る
+    fn preimage(value: &serde_json::Value, salt: &str) -> String {
+        value.to_string() + salt
+    }
+
+    // cell_salt: 乱数の種、レコード、カラムからセルのソルトを導く
+    fn cell_salt(seed: &[u8; 32], record: usize, column: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update((record as u64).to_be_bytes());
+        hasher.update(column.as_bytes());
+        Self::gen_salt(&mut StdRng::from_seed(hasher.finalize().into()))
     }
 
     // gen_salt: 16 文字の乱数を生成する
-    fn gen_salt() -> String {
+    fn gen_salt(rng: &mut impl Rng) -> String {
         const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ\
                             abcdefghijklmnopqrstuvwxyz\
                             0123456789)(*&^%$#@!~";
-        let mut rng = rand::thread_rng();
 
         (0..16)
             .map(|_| {
@@ -358,11 +1524,93 @@ impl Zk4log {
             .collect()
     }
 
+    // gen_log_id: proof を結び付けるログの ID として 16 バイトの乱数を生成する
+    fn gen_log_id() -> String {
+        hex::encode(rand::thread_rng().gen::<[u8; 16]>())
+    }
+
+    // log_format: --format があればそれに従い、なければ拡張子からログの形式を決める
+    // (CSV の区切り文字は --delimiter、アクセスログの書式は --log-format で変えられる)
+    fn log_format(call: &EvaluatedCall, path: &str) -> Result<Format, LabeledError> {
+        let format = match call.get_flag::<String>("format")? {
+            Some(name) => Format::parse(&name).ok_or_else(|| LabeledError {
+                label: "Invalid log format".into(),
+                msg: format!("--format must be {}", Format::NAMES),
+                span: Some(call.head),
+            })?,
+            None if call.has_flag("log-format") => Format::Access(LogFormat::combined()),
+            None => Format::detect(path),
+        };
+
+        if let Some(log_format) = call.get_flag::<String>("log-format")? {
+            let Format::Access(_) = format else {
+                return Err(LabeledError {
+                    label: "Invalid log format".into(),
+                    msg: "--log-format is only used with access logs".into(),
+                    span: Some(call.head),
+                });
+            };
+            let log_format = LogFormat::new(&log_format).map_err(|e| LabeledError {
+                label: "Invalid log format".into(),
+                msg: e,
+                span: Some(call.head),
+            })?;
+            return Ok(Format::Access(log_format));
+        }
+
+        let Some(delimiter) = call.get_flag::<String>("delimiter")? else {
+            return Ok(format);
+        };
+        let delimiter = match delimiter.as_str() {
+            "\\t" | "tab" => "\t",
+            delimiter => delimiter,
+        };
+        match (format, delimiter.as_bytes()) {
+            (Format::Csv(_), &[delimiter]) => Ok(Format::Csv(delimiter)),
+            (Format::Csv(_), _) => Err(LabeledError {
+                label: "Invalid delimiter".into(),
+                msg: "--delimiter must be a single ASCII character".into(),
+                span: Some(call.head),
+            }),
+            _ => Err(LabeledError {
+                label: "Invalid delimiter".into(),
+                msg: "--delimiter is only used with CSV and TSV logs".into(),
+                span: Some(call.head),
+            }),
+        }
+    }
+
+    // records: ログファイルのレコード (オブジェクト) を1つずつ読み込む
+    fn records(
+        path: &str,
+        format: &Format,
+        call: &EvaluatedCall,
+    ) -> Result<impl Iterator<Item = Result<serde_json::Value, LabeledError>>, LabeledError> {
+        let records = records::read(path, format);
+        let (path, head) = (path.to_string(), call.head);
+        let error = move |e: io::Error| LabeledError {
+            label: "Invalid log file".into(),
+            msg: format!("{}: {}", path, e),
+            span: Some(head),
+        };
+        let records = records.map_err(&error)?;
+        Ok(records.map(move |record| record.map_err(&error)))
+    }
+
+    // read_json_records: ログファイルを読み込み、レコード (オブジェクト) の配列にする
+    fn read_json_records(
+        path: &str,
+        call: &EvaluatedCall,
+    ) -> Result<Vec<serde_json::Value>, LabeledError> {
+        let format = Self::log_format(call, path)?;
+        Self::records(path, &format, call)?.collect()
+    }
+
     fn expand_tilde_and_check_file_exists(
         file: &str,
         call: &EvaluatedCall,
     ) -> Result<String, LabeledError> {
-        let path = expand_tilde(&file);
+        let path = expand_tilde(file);
         if !path.exists() {
             eprintln!("File not found: {}", path.display());
 
@@ -378,9 +1626,693 @@ impl Zk4log {
 
     pub fn zk4log(&self, call: &EvaluatedCall, _input: &Value) -> Result<Value, LabeledError> {
         Err(LabeledError {
-            label: "subcommand in [\"hide\", \"verify\", \"open\"]".into(),
+            label: "subcommand in [\"hide\", \"verify\", \"open\", \"keygen\", \"reveal\", \"check-reveal\", \"scan\"]"
+                .into(),
             msg: "zk4log <subcommand>".into(),
             span: Some(call.head),
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nu_protocol::Spanned;
+    use std::io::Read;
+
+    // call: nu から呼ばれたときと同じ EvaluatedCall を作る
+    fn call(positional: &[&str], named: &[(&str, Option<&str>)]) -> EvaluatedCall {
+        let span = Span::test_data();
+        EvaluatedCall {
+            head: span,
+            positional: positional.iter().map(|p| Value::string(*p, span)).collect(),
+            named: named
+                .iter()
+                .map(|(name, value)| {
+                    let name = Spanned {
+                        item: name.to_string(),
+                        span,
+                    };
+                    (name, value.map(|value| Value::string(value, span)))
+                })
+                .collect(),
+        }
+    }
+
+    // Fixture: 一時ディレクトリに鍵を用意し、小さなログを秘匿化する
+    struct Fixture {
+        dir: TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let dir = TempDir::new().unwrap();
+            let (params, _pvk) = zk::setup(zk::Hash::Sha256d, 1);
+            keys::write_params(&dir.path("zk4log.params"), zk::Hash::Sha256d, 1, &params).unwrap();
+            keys::write_vk(&dir.path("key.pub"), &[(zk::Hash::Sha256d, &params.vk)]).unwrap();
+            Fixture { dir }
+        }
+
+        // add_keys: 別のハッシュの鍵を <ハッシュ名>.params と <ハッシュ名>.pub に用意する
+        fn add_keys(&self, hash: zk::Hash) {
+            let (params, _pvk) = zk::setup(hash, 1);
+            let name = |ext: &str| self.path(&format!("{}.{}", hash.name(), ext));
+            keys::write_params(&name("params"), hash, 1, &params).unwrap();
+            keys::write_vk(&name("pub"), &[(hash, &params.vk)]).unwrap();
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.dir.path(name)
+        }
+
+        // hide: log.ndjson の user カラムを秘匿化し、output.ndjson と out.proof に書き出す
+        fn hide(&self, log: &str) {
+            self.hide_with(log, &[("columns", Some("user"))]);
+        }
+
+        fn hide_with(&self, log: &str, flags: &[(&str, Option<&str>)]) {
+            fs::write(self.path("log.ndjson"), log).unwrap();
+            let (params, output, proof) = (
+                self.path("zk4log.params"),
+                self.path("output.ndjson"),
+                self.path("out.proof"),
+            );
+            // flags を先に置き、既定のフラグを上書きできるようにする
+            let mut named = flags.to_vec();
+            named.extend_from_slice(&[
+                ("params", Some(params.as_str())),
+                ("output", Some(output.as_str())),
+                ("proof", Some(proof.as_str())),
+            ]);
+            let call = call(&[&self.path("log.ndjson")], &named);
+            Zk4log.hide(&call, &Value::nothing(call.head)).unwrap();
+        }
+
+        // verify: 検証結果のうち ok でない行の (record, column, status) を返す
+        fn verify(&self) -> Vec<(Option<u64>, Option<String>, String)> {
+            let report = self.verify_with(&[]).unwrap();
+            Self::failures(&serde_json::from_str(&report.as_string().unwrap()).unwrap())
+        }
+
+        fn verify_with(&self, flags: &[(&str, Option<&str>)]) -> Result<Value, LabeledError> {
+            let (json, proof, key) = (
+                self.path("output.ndjson"),
+                self.path("out.proof"),
+                self.path("key.pub"),
+            );
+            let mut named = flags.to_vec();
+            named.extend_from_slice(&[
+                ("json", Some(json.as_str())),
+                ("proof", Some(proof.as_str())),
+                ("key", Some(key.as_str())),
+                ("report-format", Some("json")),
+            ]);
+            let call = call(&[], &named);
+            Zk4log.verify(&call, &Value::nothing(call.head))
+        }
+
+        fn failures(report: &serde_json::Value) -> Vec<(Option<u64>, Option<String>, String)> {
+            report
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|row| row["status"] != "ok")
+                .map(|row| {
+                    (
+                        row["record"].as_u64(),
+                        row["column"].as_str().map(str::to_string),
+                        row["status"].as_str().unwrap().to_string(),
+                    )
+                })
+                .collect()
+        }
+
+        // edit: ファイルの各行を書き換える
+        fn edit(&self, name: &str, f: impl Fn(usize, &str) -> Option<String>) {
+            let data = fs::read_to_string(self.path(name)).unwrap();
+            let lines: Vec<String> = data
+                .lines()
+                .enumerate()
+                .filter_map(|(i, line)| f(i, line))
+                .collect();
+            fs::write(self.path(name), lines.join("\n") + "\n").unwrap();
+        }
+    }
+
+    fn row(
+        record: Option<u64>,
+        column: Option<&str>,
+        status: &str,
+    ) -> (Option<u64>, Option<String>, String) {
+        (record, column.map(str::to_string), status.to_string())
+    }
+
+    const LOG: &str = "{\"user\":\"alice\",\"n\":1}\n{\"user\":\"bob\",\"n\":2}\n{\"n\":3}\n";
+
+    #[test]
+    fn test_verify_whole_log() {
+        let fixture = Fixture::new();
+        fixture.hide(LOG);
+        assert_eq!(fixture.verify(), vec![]);
+        let output = fs::read_to_string(fixture.path("output.ndjson")).unwrap();
+        let proof = fs::read_to_string(fixture.path("out.proof")).unwrap();
+
+        // 秘匿化したセルのない最後のレコードを書き換える
+        fixture.edit("output.ndjson", |i, line| {
+            Some(if i == 2 {
+                line.replace('3', "4")
+            } else {
+                line.to_string()
+            })
+        });
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(2), None, "record changed"),
+                row(None, None, "log changed")
+            ]
+        );
+
+        // proof ファイルのコミットメントも書き換えたログに合わせると、proof が通らなくなる
+        let records: Vec<serde_json::Value> = fs::read_to_string(fixture.path("output.ndjson"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let mut chain = Chain::new(
+            &proof_file::Reader::open(&fixture.path("out.proof"))
+                .unwrap()
+                .header
+                .log_id,
+        );
+        for (record, hidden) in records.iter().zip([&["user"][..], &["user"], &[]]) {
+            let hidden: Vec<String> = hidden.iter().map(|h| h.to_string()).collect();
+            chain.push(&commitment::record(record), &hidden);
+        }
+        fixture.edit("out.proof", |_, line| {
+            let mut line: serde_json::Value = serde_json::from_str(line).unwrap();
+            match line["type"].as_str() {
+                Some("header") => line["log"] = hex::encode(chain.head()).into(),
+                Some("record") => {
+                    let idx = line["record"].as_u64().unwrap() as usize;
+                    line["commitment"] = hex::encode(commitment::record(&records[idx])).into();
+                }
+                _ => (),
+            }
+            Some(line.to_string())
+        });
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(0), Some("user"), "bad proof"),
+                row(Some(1), Some("user"), "bad proof")
+            ]
+        );
+
+        // 末尾のレコードを切り捨てる
+        fs::write(fixture.path("out.proof"), &proof).unwrap();
+        fs::write(fixture.path("output.ndjson"), &output).unwrap();
+        fixture.edit("output.ndjson", |i, line| (i < 2).then(|| line.to_string()));
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(2), None, "out of range"),
+                row(None, None, "records missing"),
+                row(None, None, "log changed"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_hidden_cells() {
+        let fixture = Fixture::new();
+        fixture.hide(LOG);
+        let output = fs::read_to_string(fixture.path("output.ndjson")).unwrap();
+        let proof = fs::read_to_string(fixture.path("out.proof")).unwrap();
+        // end_count: proof の数を合わせて、切り捨てられたファイルに見えないようにする
+        let end_count = |line: &str| line.replace("\"count\":2", "\"count\":1");
+
+        // 秘匿化したセルの proof を消しても、proof ファイルの記録から見つかる
+        fixture.edit("out.proof", |_, line| {
+            (!line.contains("\"type\":\"proof\",\"record\":0")).then(|| end_count(line))
+        });
+        assert_eq!(
+            fixture.verify(),
+            vec![row(Some(0), Some("user"), "unproven cell")]
+        );
+
+        // 秘匿化したセルの記録まで消すと、ログ全体のコミットメントが合わなくなる
+        fixture.edit("out.proof", |_, line| {
+            let mut line: serde_json::Value = serde_json::from_str(line).unwrap();
+            if line["type"] == "record" && line["record"] == 0 {
+                line.as_object_mut().unwrap().remove("hidden");
+            }
+            Some(line.to_string())
+        });
+        let failures = fixture.verify();
+        assert!(
+            failures.contains(&row(None, None, "log changed")),
+            "{:?}",
+            failures
+        );
+
+        // proof が指すセルがログにない
+        fs::write(fixture.path("out.proof"), &proof).unwrap();
+        fixture.edit("output.ndjson", |i, line| {
+            Some(if i == 1 {
+                "{\"n\":2}".to_string()
+            } else {
+                line.to_string()
+            })
+        });
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(1), None, "record changed"),
+                row(Some(1), Some("user"), "orphan proof"),
+                row(None, None, "log changed"),
+            ]
+        );
+        fs::write(fixture.path("output.ndjson"), &output).unwrap();
+    }
+
+    #[test]
+    fn test_verify_out_of_order() {
+        let fixture = Fixture::new();
+        fixture.hide(LOG);
+        assert_eq!(fixture.verify(), vec![]);
+
+        // proof ファイルは1行ずつログと突き合わせるので、
+        // 後ろのレコードの行より後にある行は順番が入れ替わっている
+        let proof = fs::read_to_string(fixture.path("out.proof")).unwrap();
+        let mut lines: Vec<&str> = proof.lines().collect();
+        let moved = lines.remove(2);
+        lines.insert(4, moved);
+        fs::write(fixture.path("out.proof"), lines.join("\n")).unwrap();
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(0), Some("user"), "unproven cell"),
+                row(Some(0), Some("user"), "out of order"),
+            ]
+        );
+
+        // 壊れた proof ファイルは、途中まで読んでいても受け付けない
+        fs::write(fixture.path("out.proof"), lines[..4].join("\n")).unwrap();
+        let error = fixture.verify_with(&[]).unwrap_err();
+        assert_eq!(error.label, "Invalid proof file");
+    }
+
+    #[test]
+    fn test_verify_hash_and_policy() {
+        let fixture = Fixture::new();
+        fs::write(fixture.path("policy.toml"), "[[rule]]\nfield = \"user\"\n").unwrap();
+        fs::write(fixture.path("other.toml"), "[[rule]]\nfield = \"n\"\n").unwrap();
+        fixture.hide_with(LOG, &[("policy", Some(&fixture.path("policy.toml")))]);
+        let policy = fixture.path("policy.toml");
+        assert!(fixture.verify_with(&[("policy", Some(&policy))]).is_ok());
+
+        // 別のポリシーで秘匿化されたとは認めない
+        let other = fixture.path("other.toml");
+        let error = fixture
+            .verify_with(&[("policy", Some(&other))])
+            .unwrap_err();
+        assert_eq!(error.label, "Policy mismatch");
+
+        // 秘匿化したセルを別のハッシュ値に書き換える
+        fixture.edit("output.ndjson", |i, line| {
+            let mut record: serde_json::Value = serde_json::from_str(line).unwrap();
+            if i == 0 {
+                record["user"] = "00".repeat(32).into();
+            }
+            Some(record.to_string())
+        });
+        assert_eq!(
+            fixture.verify(),
+            vec![
+                row(Some(0), None, "record changed"),
+                row(Some(0), Some("user"), "hash mismatch"),
+                row(None, None, "log changed"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_verify_hash_per_rule() {
+        let fixture = Fixture::new();
+        fixture.add_keys(zk::Hash::Blake2s);
+        fs::write(
+            fixture.path("policy.toml"),
+            "[[rule]]\nfield = \"user\"\nhash = \"blake2s\"\n\n[[rule]]\nfield = \"n\"\n",
+        )
+        .unwrap();
+        let policy = fixture.path("policy.toml");
+        let params = format!(
+            "{},{}",
+            fixture.path("zk4log.params"),
+            fixture.path("blake2s.params")
+        );
+        fixture.hide_with(LOG, &[("policy", Some(&policy)), ("params", Some(&params))]);
+
+        // 各セルは、そのハッシュの鍵で検証する
+        let keys = format!(
+            "{},{}",
+            fixture.path("key.pub"),
+            fixture.path("blake2s.pub")
+        );
+        let report = fixture.verify_with(&[("key", Some(&keys))]).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report.as_string().unwrap()).unwrap();
+        assert_eq!(Fixture::failures(&report), vec![]);
+        let hidden = fs::read_to_string(fixture.path("output.ndjson")).unwrap();
+        let record: serde_json::Value =
+            serde_json::from_str(hidden.lines().next().unwrap()).unwrap();
+        assert_ne!(record["user"], "alice");
+        assert_ne!(record["n"], 1);
+
+        // 使ったハッシュの鍵が揃っていなければ検証しない
+        let error = fixture.verify_with(&[]).unwrap_err();
+        assert_eq!(error.label, "Missing key");
+
+        // 使うハッシュの params がなければ秘匿化しない
+        let call = call(
+            &[&fixture.path("log.ndjson")],
+            &[
+                ("policy", Some(&policy)),
+                ("params", Some(&fixture.path("zk4log.params"))),
+                ("output", Some(&fixture.path("output.ndjson"))),
+                ("proof", Some(&fixture.path("out.proof"))),
+            ],
+        );
+        let error = Zk4log.hide(&call, &Value::nothing(call.head)).unwrap_err();
+        assert_eq!(error.label, "Missing params");
+    }
+
+    #[test]
+    fn test_table() {
+        let fixture = Fixture::new();
+        let span = Span::test_data();
+        let table = Value::list(
+            ["alice", "bob"]
+                .iter()
+                .enumerate()
+                .map(|(i, user)| {
+                    Value::record(
+                        vec!["user".to_string(), "n".to_string()],
+                        vec![Value::string(*user, span), Value::int(i as i64, span)],
+                        span,
+                    )
+                })
+                .collect(),
+            span,
+        );
+        let (params, proof, key) = (
+            fixture.path("zk4log.params"),
+            fixture.path("out.proof"),
+            fixture.path("key.pub"),
+        );
+
+        // パイプラインのテーブルを秘匿化すると、秘匿化したテーブルが返る
+        let hide = call(
+            &[],
+            &[
+                ("columns", Some("user")),
+                ("params", Some(&params)),
+                ("proof", Some(&proof)),
+            ],
+        );
+        let hidden = Zk4log.hide(&hide, &table).unwrap();
+        let rows = hidden.as_list().unwrap();
+        assert_eq!(rows.len(), 2);
+        let user = rows[0]
+            .get_data_by_key("user")
+            .unwrap()
+            .as_string()
+            .unwrap();
+        assert_eq!(user.len(), 64);
+        assert_eq!(rows[0].get_data_by_key("n"), Some(Value::int(0, span)));
+
+        // 秘匿化したテーブルはそのままパイプラインで検証できる
+        let verify = |input: &Value| {
+            let call = call(
+                &[],
+                &[
+                    ("proof", Some(&proof)),
+                    ("key", Some(&key)),
+                    ("report-format", Some("json")),
+                ],
+            );
+            let report = Zk4log.verify(&call, input).unwrap();
+            Fixture::failures(&serde_json::from_str(&report.as_string().unwrap()).unwrap())
+        };
+        assert_eq!(verify(&hidden), vec![]);
+        let edited = Value::list(rows[..1].to_vec(), span);
+        assert_eq!(
+            verify(&edited),
+            vec![
+                row(Some(1), None, "out of range"),
+                row(Some(1), Some("user"), "out of range"),
+                row(None, None, "records missing"),
+                row(None, None, "log changed"),
+            ]
+        );
+
+        // テーブルは返すので --output は使わない、テーブルでない入力も受け付けない
+        let output = fixture.path("output.ndjson");
+        let with_output = call(&[], &[("columns", Some("user")), ("output", Some(&output))]);
+        let error = Zk4log.hide(&with_output, &table).unwrap_err();
+        assert_eq!(error.label, "Unexpected --output");
+        let error = Zk4log.hide(&hide, &Value::int(1, span)).unwrap_err();
+        assert_eq!(error.label, "No log given");
+    }
+
+    #[test]
+    fn test_bundle_signature() {
+        let fixture = Fixture::new();
+        fs::write(fixture.path("log.ndjson"), LOG).unwrap();
+        fs::create_dir(fixture.path("signers")).unwrap();
+        let (sign_key, other) = (signing::generate(), signing::generate());
+        signing::write_signing_key(&fixture.path("collector.key"), &sign_key).unwrap();
+        let (bundle, params, signers) = (
+            fixture.path("log.bundle"),
+            fixture.path("zk4log.params"),
+            fixture.path("signers"),
+        );
+        let signer_key = fixture.path("signers/collector.pub");
+        let other_key = fixture.path("signers/other.pub");
+
+        // 署名したバンドルを作る
+        let hide = call(
+            &[&fixture.path("log.ndjson")],
+            &[
+                ("columns", Some("user")),
+                ("params", Some(&params)),
+                ("bundle", Some(&bundle)),
+                ("sign-key", Some(&fixture.path("collector.key"))),
+            ],
+        );
+        Zk4log.hide(&hide, &Value::nothing(hide.head)).unwrap();
+
+        // verify: 署名の行だけを (status, signer) で返す
+        let verify = || {
+            let call = call(
+                &[&bundle],
+                &[
+                    ("trusted-signers", Some(&signers)),
+                    ("report-format", Some("json")),
+                ],
+            );
+            let report = Zk4log.verify(&call, &Value::nothing(call.head)).unwrap();
+            let report: serde_json::Value =
+                serde_json::from_str(&report.as_string().unwrap()).unwrap();
+            let rows = report.as_array().unwrap();
+            assert!(rows
+                .iter()
+                .filter(|row| row.get("signer").is_none())
+                .all(|row| row["status"] == "ok"));
+            rows.iter()
+                .filter(|row| row.get("signer").is_some())
+                .map(|row| (row["status"].clone(), row["signer"].clone()))
+                .collect::<Vec<_>>()
+        };
+        let status = |status: &str, signer: &str| {
+            vec![(serde_json::json!(status), serde_json::json!(signer))]
+        };
+
+        // 信頼する署名者の鍵がなければ untrusted signer、あれば署名者の名前を報告する
+        signing::write_public_key(&other_key, &other.verifying_key()).unwrap();
+        assert_eq!(
+            verify(),
+            status(
+                "untrusted signer",
+                &signing::fingerprint(&sign_key.verifying_key())
+            )
+        );
+        signing::write_public_key(&signer_key, &sign_key.verifying_key()).unwrap();
+        assert_eq!(verify(), status("ok", "collector"));
+
+        // 別のデータへの署名に差し替えると bad signature
+        let mut archive = tar::Archive::new(fs::File::open(&bundle).unwrap());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let name = entry.path().unwrap().to_str().unwrap().to_string();
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data).unwrap();
+                (name, data)
+            })
+            .collect::<Vec<_>>();
+        let mut builder = tar::Builder::new(fs::File::create(&bundle).unwrap());
+        for (name, mut data) in entries {
+            if name == bundle::SIGNATURE {
+                data = serde_json::to_vec(&signing::sign(&sign_key, b"other")).unwrap();
+            }
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &data[..]).unwrap();
+        }
+        builder.finish().unwrap();
+        assert_eq!(
+            verify(),
+            vec![(serde_json::json!("bad signature"), serde_json::Value::Null)]
+        );
+    }
+
+    #[test]
+    fn test_reveal() {
+        use age::secrecy::ExposeSecret;
+
+        let dir = TempDir::new().unwrap();
+        let path = |name: &str| dir.path(name);
+
+        // hide と同じく、user カラムを秘匿化したログ、保管庫、proof ファイルを用意する
+        let log = serde_json::json!([{"user": "alice", "n": 1}, {"user": "bob", "n": 2}]);
+        let identity = age::x25519::Identity::generate();
+        fs::write(path("identity.txt"), identity.to_string().expose_secret()).unwrap();
+        let mut vault = VaultWriter::create(
+            &path("output.json.vault"),
+            &VaultKey::Recipient(identity.to_public()),
+            "log",
+            zk::Hash::Sha256d.name(),
+        )
+        .unwrap();
+        let mut hidden = log.clone();
+        // 開示する 2 番目のレコードは、ポリシーで別のハッシュを指定されたものとする
+        let hashes = [zk::Hash::Sha256d, zk::Hash::Blake2s];
+        for (idx, record) in hidden.as_array_mut().unwrap().iter_mut().enumerate() {
+            let salt = Zk4log::gen_salt(&mut rand::thread_rng());
+            let preimage = Zk4log::preimage(&record["user"], &salt);
+            record["user"] = hex::encode(hashes[idx].digest(preimage.as_bytes())).into();
+            vault.push(idx, "user", &salt, hashes[idx].name()).unwrap();
+        }
+        vault.finish().unwrap();
+        fs::write(path("log.json"), log.to_string()).unwrap();
+        fs::write(path("output.json"), hidden.to_string()).unwrap();
+        let write_proof = |log_id: &str, hashes: &[zk::Hash]| {
+            let circuits = hashes
+                .iter()
+                .map(|hash| (hash.name().to_string(), hash.circuit_id(1)))
+                .collect();
+            let header = Header::new(circuits, log_id.to_string());
+            ProofWriter::create(&path("out.proof"), header)
+                .unwrap()
+                .finish()
+                .unwrap();
+        };
+        write_proof("log", &hashes);
+
+        let reveal = call(
+            &[&path("log.json")],
+            &[
+                ("vault", Some(&path("output.json.vault"))),
+                ("identity", Some(&path("identity.txt"))),
+                ("records", Some("1")),
+                ("output", Some(&path("opening.json"))),
+            ],
+        );
+        Zk4log
+            .reveal(&reveal, &Value::nothing(reveal.head))
+            .unwrap();
+        let opening = Opening::read(&path("opening.json")).unwrap();
+        assert_eq!(opening.extension.as_deref(), Some("json"));
+
+        let check_reveal = || {
+            let call = call(
+                &[&path("opening.json")],
+                &[
+                    ("json", Some(&path("output.json"))),
+                    ("proof", Some(&path("out.proof"))),
+                ],
+            );
+            Zk4log.check_reveal(&call, &Value::nothing(call.head))
+        };
+        let statuses = || -> Vec<String> {
+            let rows = check_reveal().unwrap();
+            rows.as_list()
+                .unwrap()
+                .iter()
+                .map(|row| row.get_data_by_key("status").unwrap().as_string().unwrap())
+                .collect()
+        };
+        assert_eq!(statuses(), vec!["ok"]);
+        assert_eq!(opening.cells[0].hash.as_deref(), Some("blake2s"));
+
+        // 開示されたセルの秘匿化した値を書き換える
+        hidden[1]["user"] = "00".repeat(32).into();
+        fs::write(path("output.json"), hidden.to_string()).unwrap();
+        assert_eq!(statuses(), vec!["hash mismatch"]);
+
+        // 同じ内容でも、別に秘匿化したログの proof ファイルとは突き合わせない
+        write_proof("other", &hashes);
+        let error = check_reveal().unwrap_err();
+        assert_eq!(error.label, "Opening does not match log");
+
+        // セルのハッシュで秘匿化していないログの proof ファイルとも突き合わせない
+        write_proof("log", &[zk::Hash::Sha256d]);
+        let error = check_reveal().unwrap_err();
+        assert_eq!(error.label, "Opening does not match log");
+    }
+
+    #[test]
+    fn test_open() {
+        let dir = TempDir::new().unwrap();
+        let path = &dir.path("log.ndjson");
+        fs::write(path, "{\"user\":\"alice\",\"n\":1}\n{\"n\":2}\n").unwrap();
+
+        // 選んだカラムだけのテーブルを返し、値のないセルは nothing になる
+        let call = call(&[path], &[("columns", Some("user"))]);
+        let table = Zk4log.open(&call, &Value::nothing(call.head)).unwrap();
+        let span = Span::test_data();
+        let row = |user: Value| Value::record(vec!["user".to_string()], vec![user], span);
+        assert_eq!(
+            table,
+            Value::list(
+                vec![row(Value::string("alice", span)), row(Value::nothing(span))],
+                span
+            )
+        );
+    }
+
+    #[test]
+    fn test_nu_value() {
+        let span = Span::test_data();
+        let json: serde_json::Value =
+            serde_json::from_str(r#"[1, -2, 1.5, 18446744073709551615]"#).unwrap();
+        assert_eq!(
+            Zk4log::nu_value(&json, span),
+            Value::list(
+                vec![
+                    Value::int(1, span),
+                    Value::int(-2, span),
+                    Value::float(1.5, span),
+                    // u64::MAX は float にせず、そのままの数字で返す
+                    Value::string("18446744073709551615", span),
+                ],
+                span
+            )
+        );
+    }
+}